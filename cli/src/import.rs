@@ -0,0 +1,518 @@
+//! Bulk import of CSV and newline-delimited JSON files.
+//!
+//! How rows turn into datoms is described by a mapping file, e.g.:
+//!
+//! ```json
+//! {
+//!   "eid": {"Column": "id"},
+//!   "attributes": [
+//!     {"column": "name", "attribute": ":person/name"},
+//!     {"column": "age", "attribute": ":person/age", "kind": "Number"}
+//!   ]
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use declarative_client::Client;
+use declarative_dataflow::{Aid, Datom, Eid, Error, Uuid, Value};
+
+/// Eids derived by hashing have this bit set, which numeric eids
+/// therefore must not have, s.t. the two can't collide.
+const HASHED_EID: Eid = 1 << 63;
+
+/// Supported input formats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Format {
+    /// Comma-separated values.
+    Csv,
+    /// One json object per line.
+    Json,
+}
+
+impl Format {
+    /// Guesses the format from a file name.
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.ends_with(".csv") {
+            Some(Format::Csv)
+        } else if path.ends_with(".json") || path.ends_with(".jsonl") || path.ends_with(".ndjson") {
+            Some(Format::Json)
+        } else {
+            None
+        }
+    }
+}
+
+/// How each row's entity id is determined.
+#[derive(Clone, Debug, Deserialize)]
+pub enum EidMapping {
+    /// Eids are taken from a column. Non-numeric values are hashed,
+    /// s.t. equal values map to the same entity, across runs and
+    /// releases alike.
+    Column(String),
+    /// Eids are assigned sequentially, starting at the given one.
+    Generate(Eid),
+}
+
+/// The type of values in a column.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Kind {
+    /// Plain strings.
+    String,
+    /// 64 bit signed integers.
+    Number,
+    /// Booleans.
+    Bool,
+    /// Entity ids, i.e. references to other entities.
+    Eid,
+    /// Attribute identifiers.
+    Aid,
+    /// Milliseconds since the epoch.
+    Instant,
+    /// Uuids.
+    Uuid,
+}
+
+/// Maps a single column onto an attribute.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AttributeMapping {
+    /// Name of the column (CSV) or field (JSON). CSV files without
+    /// headers are addressed by column index.
+    pub column: String,
+    /// The attribute values are transacted on.
+    pub attribute: Aid,
+    /// The type of values. Defaults to strings for CSV files, and to
+    /// the corresponding json type otherwise.
+    pub kind: Option<Kind>,
+}
+
+/// Describes how rows turn into datoms.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Mapping {
+    /// The input format, guessed from the file name if not given.
+    pub format: Option<Format>,
+    /// Whether the first row of a CSV file names the columns.
+    /// Defaults to true.
+    pub has_headers: Option<bool>,
+    /// How each row's entity id is determined.
+    pub eid: EidMapping,
+    /// Which columns to import, into which attributes.
+    pub attributes: Vec<AttributeMapping>,
+}
+
+impl Mapping {
+    /// Reads a mapping from a json file.
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let mut contents = String::new();
+
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|error| Error::fault(format!("Failed to read mapping: {}", error)))?;
+
+        serde_json::from_str(&contents).map_err(Error::incorrect)
+    }
+}
+
+/// Knobs for streaming batches to the server.
+pub struct Options {
+    /// Maximum number of datoms per transaction.
+    pub batch_size: usize,
+    /// How often a failed transaction is retried.
+    pub retries: usize,
+}
+
+/// Turns rows into datoms.
+struct Importer {
+    mapping: Mapping,
+    next_eid: Eid,
+}
+
+impl Importer {
+    fn new(mapping: Mapping) -> Self {
+        let next_eid = match mapping.eid {
+            EidMapping::Generate(start) => start,
+            EidMapping::Column(_) => 0,
+        };
+
+        Importer { mapping, next_eid }
+    }
+
+    /// Converts a row, given as a lookup from column names to json
+    /// values.
+    fn datoms<F>(&mut self, lookup: F) -> Result<Vec<Datom<Aid>>, Error>
+    where
+        F: Fn(&str) -> Option<serde_json::Value>,
+    {
+        let eid = match self.mapping.eid {
+            EidMapping::Generate(_) => {
+                let eid = self.next_eid;
+                self.next_eid += 1;
+                eid
+            }
+            EidMapping::Column(ref column) => match lookup(column) {
+                None | Some(serde_json::Value::Null) => {
+                    return Err(Error::incorrect(format!("Missing eid column {}.", column)));
+                }
+                Some(value) => derive_eid(&value)?,
+            },
+        };
+
+        let mut datoms = Vec::with_capacity(self.mapping.attributes.len());
+
+        for mapping in self.mapping.attributes.iter() {
+            match lookup(&mapping.column) {
+                None | Some(serde_json::Value::Null) => {}
+                Some(serde_json::Value::String(ref string)) if string.is_empty() => {}
+                Some(value) => {
+                    let value = convert(&value, mapping.kind).map_err(|error| {
+                        Error::incorrect(format!("Column {}: {}", mapping.column, error.message))
+                    })?;
+
+                    datoms.push(Datom::add(eid, mapping.attribute.clone(), value));
+                }
+            }
+        }
+
+        Ok(datoms)
+    }
+}
+
+/// Derives an entity id from a column value.
+fn derive_eid(value: &serde_json::Value) -> Result<Eid, Error> {
+    let string = match value {
+        serde_json::Value::String(string) => string.clone(),
+        other => other.to_string(),
+    };
+
+    match value.as_u64().or_else(|| string.parse::<Eid>().ok()) {
+        Some(eid) if eid & HASHED_EID != 0 => {
+            Err(Error::incorrect(format!("Eid {} is reserved for hashed values.", eid)))
+        }
+        Some(eid) => Ok(eid),
+        None => Ok(fnv1a(string.as_bytes()) | HASHED_EID),
+    }
+}
+
+/// 64 bit FNV-1a. Unlike the standard library's hashers, it is
+/// guaranteed to stay the same.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Converts a json value into a value of the given kind.
+fn convert(value: &serde_json::Value, kind: Option<Kind>) -> Result<Value, Error> {
+    let string = match value {
+        serde_json::Value::String(string) => string.clone(),
+        other => other.to_string(),
+    };
+
+    let parsed = match kind {
+        None => match value {
+            serde_json::Value::Bool(boolean) => Some(Value::Bool(*boolean)),
+            serde_json::Value::Number(number) => number.as_i64().map(Value::Number),
+            serde_json::Value::String(string) => Some(Value::String(string.clone())),
+            _ => None,
+        },
+        Some(Kind::String) => Some(Value::String(string.clone())),
+        Some(Kind::Number) => string.trim().parse().ok().map(Value::Number),
+        Some(Kind::Bool) => string.trim().parse().ok().map(Value::Bool),
+        Some(Kind::Eid) => string.trim().parse().ok().map(Value::Eid),
+        Some(Kind::Aid) => Some(Value::Aid(string.clone())),
+        Some(Kind::Instant) => string.trim().parse().ok().map(Value::Instant),
+        Some(Kind::Uuid) => Uuid::parse_str(string.trim()).ok().map(Value::Uuid),
+    };
+
+    parsed.ok_or_else(|| Error::incorrect(format!("Can't convert {} to {:?}.", string, kind)))
+}
+
+/// Streams batches to the server, retrying failed ones.
+struct Batcher<'a> {
+    client: &'a mut Client,
+    options: Options,
+    batch: Vec<Datom<Aid>>,
+    rows: usize,
+    datoms: usize,
+    started: Instant,
+}
+
+impl<'a> Batcher<'a> {
+    fn push(&mut self, datoms: Vec<Datom<Aid>>) -> Result<(), Error> {
+        self.rows += 1;
+        self.batch.extend(datoms.into_iter());
+
+        if self.batch.len() >= self.options.batch_size {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut attempt = 0;
+
+        loop {
+            match self.client.transact(self.batch.clone()) {
+                Ok(_receipt) => break,
+                Err(error) => {
+                    // The server applies batches entirely or not at
+                    // all, so failed ones are safe to retry. Without
+                    // an answer though, there is no telling whether a
+                    // batch was applied, and retrying could duplicate
+                    // datoms.
+                    if declarative_client::is_unanswered(&error) {
+                        return Err(Error {
+                            category: error.category,
+                            message: format!(
+                                "Batch ending at row {} failed, it may have been applied: {}",
+                                self.rows, error.message
+                            ),
+                        });
+                    }
+
+                    if attempt >= self.options.retries {
+                        return Err(Error {
+                            category: error.category,
+                            message: format!("Giving up after row {}: {}", self.rows, error.message),
+                        });
+                    }
+
+                    attempt += 1;
+                    warn!("batch failed ({}), retry {} of {}", error.message, attempt, self.options.retries);
+                    thread::sleep(Duration::from_millis(100 << std::cmp::min(attempt, 6)));
+                }
+            }
+        }
+
+        self.datoms += self.batch.len();
+        self.batch.clear();
+
+        let elapsed = self.started.elapsed();
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_millis()) / 1000.0;
+        eprintln!(
+            "imported {} rows, {} datoms ({:.0} datoms/s)",
+            self.rows,
+            self.datoms,
+            self.datoms as f64 / elapsed.max(0.001)
+        );
+
+        Ok(())
+    }
+}
+
+/// Imports a file into the server, according to the mapping.
+pub fn run(client: &mut Client, path: &str, mapping: Mapping, options: Options) -> Result<(), Error> {
+    let format = mapping
+        .format
+        .or_else(|| Format::from_path(path))
+        .ok_or_else(|| Error::incorrect(format!("Can't tell the format of {}.", path)))?;
+
+    let has_headers = mapping.has_headers.unwrap_or(true);
+    let file = File::open(path).map_err(|error| Error::fault(format!("Failed to open {}: {}", path, error)))?;
+
+    let mut importer = Importer::new(mapping);
+    let mut batcher = Batcher {
+        client,
+        options,
+        batch: Vec::new(),
+        rows: 0,
+        datoms: 0,
+        started: Instant::now(),
+    };
+
+    for_each_row(format, has_headers, file, |lookup| {
+        let datoms = importer.datoms(lookup)?;
+        batcher.push(datoms)
+    })?;
+
+    batcher.flush()
+}
+
+/// Reads rows in the given format and hands each of them to `f`, as
+/// a lookup from column names to json values.
+fn for_each_row<R, F>(format: Format, has_headers: bool, reader: R, mut f: F) -> Result<(), Error>
+where
+    R: Read,
+    F: FnMut(&dyn Fn(&str) -> Option<serde_json::Value>) -> Result<(), Error>,
+{
+    match format {
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new().has_headers(has_headers).from_reader(reader);
+
+            let columns: HashMap<String, usize> = if has_headers {
+                reader
+                    .headers()
+                    .map_err(Error::incorrect)?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| (name.to_string(), index))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+
+            for record in reader.records() {
+                let record = record.map_err(Error::incorrect)?;
+
+                f(&|column| {
+                    let index = match columns.get(column) {
+                        Some(index) => *index,
+                        None => column.parse().ok()?,
+                    };
+
+                    record
+                        .get(index)
+                        .map(|field| serde_json::Value::String(field.to_string()))
+                })?;
+            }
+        }
+        Format::Json => {
+            for (number, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line.map_err(Error::fault)?;
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let object: serde_json::Value = serde_json::from_str(&line)
+                    .map_err(|error| Error::incorrect(format!("Line {}: {}", number + 1, error)))?;
+
+                f(&|field| object.get(field).cloned())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{derive_eid, for_each_row, Format, Importer, Mapping, HASHED_EID};
+    use declarative_dataflow::{Aid, Datom, Value};
+
+    fn mapping(json: &str) -> Mapping {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn import(mapping: Mapping, format: Format, input: &str) -> Vec<Datom<Aid>> {
+        let has_headers = mapping.has_headers.unwrap_or(true);
+        let mut importer = Importer::new(mapping);
+        let mut datoms = Vec::new();
+
+        for_each_row(format, has_headers, input.as_bytes(), |lookup| {
+            datoms.extend(importer.datoms(lookup)?);
+            Ok(())
+        })
+        .unwrap();
+
+        datoms
+    }
+
+    #[test]
+    fn test_derive_eid() {
+        assert_eq!(derive_eid(&serde_json::json!(42)).unwrap(), 42);
+        assert_eq!(derive_eid(&serde_json::json!("42")).unwrap(), 42);
+
+        // Hashes must not change between runs or releases.
+        let hashed = derive_eid(&serde_json::json!("mabel")).unwrap();
+        assert_eq!(hashed, 0x9a65_81a5_2f61_d3c2);
+        assert_ne!(hashed & HASHED_EID, 0);
+
+        assert!(derive_eid(&serde_json::json!(HASHED_EID)).is_err());
+        assert!(derive_eid(&serde_json::json!(HASHED_EID.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        let with_headers = mapping(
+            r#"{
+                "eid": {"Column": "id"},
+                "attributes": [
+                    {"column": "name", "attribute": ":name"},
+                    {"column": "age", "attribute": ":age", "kind": "Number"}
+                ]
+            }"#,
+        );
+
+        assert_eq!(
+            import(with_headers, Format::Csv, "id,name,age\n1,Mabel,12\n2,,13\n"),
+            vec![
+                Datom::add(1, ":name", Value::String("Mabel".to_string())),
+                Datom::add(1, ":age", Value::Number(12)),
+                Datom::add(2, ":age", Value::Number(13)),
+            ]
+        );
+
+        let without_headers = mapping(
+            r#"{
+                "has_headers": false,
+                "eid": {"Generate": 10},
+                "attributes": [{"column": "1", "attribute": ":name"}]
+            }"#,
+        );
+
+        assert_eq!(
+            import(without_headers, Format::Csv, "x,Mabel\ny,Dipper\n"),
+            vec![
+                Datom::add(10, ":name", Value::String("Mabel".to_string())),
+                Datom::add(11, ":name", Value::String("Dipper".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_rows() {
+        let mapping = mapping(
+            r#"{
+                "eid": {"Column": "id"},
+                "attributes": [
+                    {"column": "name", "attribute": ":name"},
+                    {"column": "twin", "attribute": ":twin?"},
+                    {"column": "friend", "attribute": ":friend", "kind": "Eid"}
+                ]
+            }"#,
+        );
+
+        let input = "{\"id\": 1, \"name\": \"Mabel\", \"twin\": true, \"friend\": \"3\"}\n\n{\"id\": 2, \"name\": null}\n";
+
+        assert_eq!(
+            import(mapping, Format::Json, input),
+            vec![
+                Datom::add(1, ":name", Value::String("Mabel".to_string())),
+                Datom::add(1, ":twin?", Value::Bool(true)),
+                Datom::add(1, ":friend", Value::Eid(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_rows() {
+        let mapping = mapping(
+            r#"{
+                "eid": {"Column": "id"},
+                "attributes": [{"column": "age", "attribute": ":age", "kind": "Number"}]
+            }"#,
+        );
+
+        let mut importer = Importer::new(mapping);
+
+        let missing_eid = for_each_row(Format::Csv, true, "age\n12\n".as_bytes(), |lookup| {
+            importer.datoms(lookup).map(|_| ())
+        });
+        assert_eq!(missing_eid.unwrap_err().category, "df.error.category/incorrect");
+
+        let not_a_number = for_each_row(Format::Csv, true, "id,age\n1,twelve\n".as_bytes(), |lookup| {
+            importer.datoms(lookup).map(|_| ())
+        });
+        assert_eq!(not_a_number.unwrap_err().category, "df.error.category/incorrect");
+    }
+}
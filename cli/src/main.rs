@@ -4,6 +4,8 @@
 #[macro_use]
 extern crate log;
 #[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate clap;
 
 use std::io::Read;
@@ -17,6 +19,12 @@ use declarative_dataflow::server::{Interest, Register, Request};
 use declarative_dataflow::sinks::{AssocIn, Sink};
 use declarative_dataflow::{Aid, Output, Rule, Datom};
 
+mod import;
+mod query;
+mod repl;
+mod schema;
+mod table;
+
 fn main() {
     env_logger::init();
 
@@ -42,6 +50,81 @@ fn main() {
         .expect("failed to connect");
     }
 
+    if let Some(_) = matches.subcommand_matches("repl") {
+        repl::run(&addr);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("import") {
+        let path = matches.value_of("FILE").unwrap();
+        let mapping = import::Mapping::from_file(matches.value_of("mapping").unwrap())
+            .expect("failed to load mapping");
+
+        let options = import::Options {
+            batch_size: matches
+                .value_of("batch-size")
+                .map(|arg| arg.parse().expect("batch size must be a usize"))
+                .unwrap_or(1000),
+            retries: matches
+                .value_of("retries")
+                .map(|arg| arg.parse().expect("retries must be a usize"))
+                .unwrap_or(3),
+        };
+
+        let mut client = declarative_client::Client::connect(&addr).expect("failed to connect");
+
+        if let Err(error) = import::run(&mut client, path, mapping, options) {
+            error!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("query") {
+        let target = match matches.value_of("NAME") {
+            Some(name) => query::Target::Named(name.to_string()),
+            None => {
+                let plan = match matches.value_of("plan") {
+                    Some(plan) => plan.to_string(),
+                    None => {
+                        let mut buf = String::new();
+                        std::io::stdin()
+                            .read_to_string(&mut buf)
+                            .expect("failed to read from stdin");
+
+                        buf
+                    }
+                };
+
+                query::Target::Plan(serde_json::from_str(&plan).expect("failed to parse plan"))
+            }
+        };
+
+        let format = query::Format::from_name(matches.value_of("format").unwrap_or("table"))
+            .expect("failed to parse format");
+
+        let mut client = declarative_client::Client::connect(&addr).expect("failed to connect");
+
+        if let Err(error) = query::run(&mut client, target, format, matches.is_present("follow")) {
+            error!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("schema") {
+        let mut client = declarative_client::Client::connect(&addr).expect("failed to connect");
+
+        let result = match matches.subcommand() {
+            ("apply", Some(matches)) => schema::from_file(matches.value_of("FILE").unwrap())
+                .and_then(|attributes| schema::apply(&mut client, attributes, matches.is_present("dry-run"))),
+            ("dump", Some(matches)) => schema::dump(&mut client, matches.value_of("FILE")),
+            _ => Err(declarative_dataflow::Error::incorrect("Expected apply or dump.")),
+        };
+
+        if let Err(error) = result {
+            error!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+
     if let Some(matches) = matches.subcommand_matches("req") {
         connect(addr.clone(), |out| {
             let reqs: Vec<Request> = match matches.value_of("REQUEST") {
@@ -128,6 +211,7 @@ fn main() {
                         plan: Plan::GraphQl(GraphQl::new(query)),
                     }],
                     publish: vec![name.to_string()],
+                    replace: None,
                 }),
                 Request::Interest(Interest {
                     name: name.to_string(),
@@ -136,6 +220,8 @@ fn main() {
                         stateful: granularity,
                     })),
                     disable_logging: None,
+                    id: None,
+                    resume_from: None,
                 }),
             ])
             .expect("failed to serialize requests");
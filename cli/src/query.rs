@@ -0,0 +1,201 @@
+//! One-shot and continuous queries, rendered for humans or other
+//! programs.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use declarative_client::Client;
+use declarative_dataflow::plan::Plan;
+use declarative_dataflow::server::Request;
+use declarative_dataflow::timestamp::Time;
+use declarative_dataflow::{Aid, Error, Rule, Value};
+
+use crate::table;
+
+/// How results are printed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// An aligned table.
+    Table,
+    /// Comma-separated values.
+    Csv,
+    /// One json document (snapshots), or one per line (diffs).
+    Json,
+}
+
+impl Format {
+    /// Parses a format name.
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            other => Err(Error::incorrect(format!("Unknown format {}.", other))),
+        }
+    }
+}
+
+/// What to query.
+pub enum Target {
+    /// A relation the server already knows.
+    Named(String),
+    /// A rule to register under a fresh name first.
+    Plan(Plan<Aid>),
+}
+
+/// Runs a query, either printing the current results once, or
+/// following all changes until interrupted. Rules registered for the
+/// query are removed again afterwards.
+pub fn run(client: &mut Client, target: Target, format: Format, follow: bool) -> Result<(), Error> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler = interrupted.clone();
+
+    ctrlc::set_handler(move || handler.store(true, Ordering::SeqCst)).map_err(Error::fault)?;
+
+    match target {
+        Target::Named(name) => show(client, &name, format, follow, &interrupted),
+        Target::Plan(plan) => {
+            let name = uuid::Uuid::new_v4().to_string();
+
+            client.register(
+                vec![Rule {
+                    name: name.clone(),
+                    plan,
+                }],
+                vec![name.clone()],
+            )?;
+
+            let result = show(client, &name, format, follow, &interrupted);
+            let unregistered = client.request(vec![Request::Unregister(name.clone())]);
+
+            result.and(unregistered.map(|_receipt| ()))
+        }
+    }
+}
+
+/// Prints the results of the named relation.
+fn show(
+    client: &mut Client,
+    name: &str,
+    format: Format,
+    follow: bool,
+    interrupted: &AtomicBool,
+) -> Result<(), Error> {
+    if follow {
+        let subscription = client.interest(name)?;
+        let mut result = Ok(());
+
+        while result.is_ok() && !interrupted.load(Ordering::SeqCst) {
+            if let Some(diffs) = subscription.next_timeout(Duration::from_millis(100)) {
+                result = print_diffs(&diffs, format);
+            }
+        }
+
+        // The interest must be gone before its rule can be removed.
+        result.and(client.uninterest(name).map(|_receipt| ()))
+    } else {
+        let snapshots = client.request_snapshot(name)?;
+        let started = Instant::now();
+
+        let snapshot = loop {
+            if interrupted.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
+            if started.elapsed() >= client.timeout() {
+                return Err(Error::fault("Timed out waiting for snapshot."));
+            }
+
+            match snapshots.recv_timeout(Duration::from_millis(100)) {
+                Ok(snapshot) => break snapshot,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::fault("Connection lost.")),
+            }
+        };
+
+        let mut relation = BTreeMap::new();
+        for (tuple, _time, diff) in snapshot.results.into_iter() {
+            *relation.entry(tuple).or_insert(0) += diff;
+        }
+
+        print_relation(&relation, &snapshot.time, format)
+    }
+}
+
+/// Prints consolidated results.
+fn print_relation(relation: &BTreeMap<Vec<Value>, isize>, time: &Time, format: Format) -> Result<(), Error> {
+    match format {
+        Format::Table => {
+            println!("{}", table::render_relation(relation));
+            eprintln!("as of {}", table::format_time(time));
+            Ok(())
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+
+            for (tuple, count) in relation.iter() {
+                for _i in 0..*count {
+                    writer
+                        .write_record(tuple.iter().map(table::format_value))
+                        .map_err(Error::fault)?;
+                }
+            }
+
+            writer.flush().map_err(Error::fault)
+        }
+        Format::Json => {
+            let rows: Vec<(&Vec<Value>, &isize)> = relation.iter().collect();
+            let document = serde_json::json!({ "time": time, "results": rows });
+
+            println!("{}", document);
+            Ok(())
+        }
+    }
+}
+
+/// Prints a batch of changes, one per line.
+fn print_diffs(diffs: &[(Vec<Value>, Time, isize)], format: Format) -> Result<(), Error> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    match format {
+        Format::Table => {
+            let rows: Vec<Vec<String>> = diffs
+                .iter()
+                .map(|(tuple, time, diff)| {
+                    let mut row = vec![format!("{:+}", diff)];
+                    row.extend(tuple.iter().map(table::format_value));
+                    row.push(format!("@ {}", table::format_time(time)));
+                    row
+                })
+                .collect();
+
+            writeln!(stdout, "{}", table::render(&[], &rows)).map_err(Error::fault)
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(stdout);
+
+            for (tuple, time, diff) in diffs.iter() {
+                let mut record = vec![format!("{:+}", diff)];
+                record.extend(tuple.iter().map(table::format_value));
+                record.push(table::format_time(time));
+
+                writer.write_record(&record).map_err(Error::fault)?;
+            }
+
+            writer.flush().map_err(Error::fault)
+        }
+        Format::Json => {
+            for (tuple, time, diff) in diffs.iter() {
+                let line = serde_json::json!({ "diff": diff, "tuple": tuple, "time": time });
+                writeln!(stdout, "{}", line).map_err(Error::fault)?;
+            }
+
+            Ok(())
+        }
+    }
+}
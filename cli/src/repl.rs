@@ -0,0 +1,258 @@
+//! An interactive shell, keeping a single connection open.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::thread;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::{Context, Editor, Helper};
+
+use declarative_client::{Client, Subscription};
+use declarative_dataflow::plan::Plan;
+use declarative_dataflow::server::Request;
+use declarative_dataflow::{Aid, AttributeConfig, Datom, Error, InputSemantics, Rule, Value};
+
+use crate::table;
+
+/// Commands understood by the shell, with their usage.
+const COMMANDS: &[(&str, &str)] = &[
+    ("help", "help"),
+    ("status", "status"),
+    ("create", "create NAME [raw|lww|distinct]"),
+    ("tx", "tx DATOMS"),
+    ("register", "register NAME PLAN"),
+    ("subscribe", "subscribe NAME"),
+    ("unsubscribe", "unsubscribe NAME"),
+    ("req", "req REQUESTS"),
+    ("quit", "quit"),
+];
+
+/// Completes commands and the names of attributes and rules known to
+/// the server.
+struct ReplHelper {
+    names: Rc<RefCell<Vec<String>>>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(' ').map(|index| index + 1).unwrap_or(0);
+        let prefix = &line[start..pos];
+
+        let candidates = if start == 0 {
+            COMMANDS
+                .iter()
+                .map(|(command, _usage)| command.to_string())
+                .filter(|command| command.starts_with(prefix))
+                .collect()
+        } else {
+            self.names
+                .borrow()
+                .iter()
+                .filter(|name| name.starts_with(prefix))
+                .cloned()
+                .collect()
+        };
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {}
+
+impl Highlighter for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Runs the shell against the server at the given address, until the
+/// user quits.
+pub fn run(addr: &str) {
+    let mut client = Client::connect(addr).expect("failed to connect");
+
+    let names = Rc::new(RefCell::new(Vec::new()));
+    refresh_names(&mut client, &names);
+
+    let mut editor = Editor::<ReplHelper>::new();
+    editor.set_helper(Some(ReplHelper {
+        names: names.clone(),
+    }));
+
+    let history = std::env::var("HOME")
+        .map(|home| format!("{}/.3dfctl_history", home))
+        .ok();
+
+    if let Some(ref history) = history {
+        editor.load_history(history).ok();
+    }
+
+    loop {
+        match editor.readline("3df> ") {
+            Ok(line) => {
+                let line = line.trim();
+
+                if line.is_empty() {
+                    continue;
+                }
+
+                editor.add_history_entry(line);
+
+                let mut parts = line.splitn(2, ' ');
+                let command = parts.next().unwrap_or("");
+                let argument = parts.next().unwrap_or("").trim();
+
+                if command == "quit" || command == "exit" {
+                    break;
+                }
+
+                match execute(&mut client, command, argument) {
+                    Err(error) => eprintln!("{}: {}", error.category, error.message),
+                    Ok(message) => {
+                        if !message.is_empty() {
+                            println!("{}", message);
+                        }
+                    }
+                }
+
+                match command {
+                    "create" | "register" | "req" => refresh_names(&mut client, &names),
+                    _ => {}
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("{}", error);
+                break;
+            }
+        }
+    }
+
+    if let Some(ref history) = history {
+        editor.save_history(history).ok();
+    }
+}
+
+/// Executes a single command, returning what to print.
+fn execute(client: &mut Client, command: &str, argument: &str) -> Result<String, Error> {
+    match command {
+        "help" => {
+            let usages: Vec<&str> = COMMANDS.iter().map(|(_command, usage)| *usage).collect();
+            Ok(usages.join("\n"))
+        }
+        "status" => {
+            let status = client.status()?;
+            serde_json::to_string_pretty(&status).map_err(Error::fault)
+        }
+        "create" => {
+            let mut parts = argument.split_whitespace();
+            let name = parts
+                .next()
+                .ok_or_else(|| Error::incorrect("An attribute name is required."))?;
+
+            let semantics = match parts.next().unwrap_or("raw") {
+                "raw" => InputSemantics::Raw,
+                "lww" => InputSemantics::LastWriteWins,
+                "distinct" => InputSemantics::Distinct,
+                other => return Err(Error::incorrect(format!("Unknown input semantics {}.", other))),
+            };
+
+            client.create_attribute(name, AttributeConfig::tx_time(semantics))?;
+            Ok(String::new())
+        }
+        "tx" => {
+            let tx_data: Vec<Datom<Aid>> = serde_json::from_str(argument).map_err(Error::incorrect)?;
+            let receipt = client.transact(tx_data)?;
+            Ok(format!("tx {} at {:?}", receipt.tx, receipt.time))
+        }
+        "register" => {
+            let mut parts = argument.splitn(2, ' ');
+            let name = parts
+                .next()
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| Error::incorrect("A rule name is required."))?;
+            let plan: Plan<Aid> = serde_json::from_str(parts.next().unwrap_or("")).map_err(Error::incorrect)?;
+
+            client.register(
+                vec![Rule {
+                    name: name.to_string(),
+                    plan,
+                }],
+                vec![name.to_string()],
+            )?;
+
+            Ok(String::new())
+        }
+        "subscribe" => {
+            let subscription = client.interest(argument)?;
+            thread::spawn(move || print_updates(subscription));
+            Ok(String::new())
+        }
+        "unsubscribe" => {
+            client.uninterest(argument)?;
+            Ok(String::new())
+        }
+        "req" => {
+            let requests: Vec<Request<Aid>> = serde_json::from_str(argument).map_err(Error::incorrect)?;
+            let receipt = client.request(requests)?;
+            Ok(format!("tx {} at {:?}", receipt.tx, receipt.time))
+        }
+        other => Err(Error::incorrect(format!("Unknown command {}, try help.", other))),
+    }
+}
+
+/// Prints the full table of results after every change.
+fn print_updates(subscription: Subscription) {
+    let name = subscription.name().to_string();
+    let mut relation: BTreeMap<Vec<Value>, isize> = BTreeMap::new();
+
+    for diffs in subscription {
+        let mut last_time = None;
+
+        for (tuple, time, diff) in diffs.into_iter() {
+            *relation.entry(tuple).or_insert(0) += diff;
+            last_time = Some(time);
+        }
+
+        let retracted: Vec<Vec<Value>> = relation
+            .iter()
+            .filter(|(_tuple, count)| **count == 0)
+            .map(|(tuple, _count)| tuple.clone())
+            .collect();
+
+        for tuple in retracted.iter() {
+            relation.remove(tuple);
+        }
+
+        println!("\n{} @ {:?}\n{}", name, last_time, table::render_relation(&relation));
+    }
+}
+
+/// Learns the names of all attributes and rules from the server.
+fn refresh_names(client: &mut Client, names: &Rc<RefCell<Vec<String>>>) {
+    let status = match client.status() {
+        Err(error) => {
+            warn!("failed to learn names: {:?}", error);
+            return;
+        }
+        Ok(status) => status,
+    };
+
+    let mut learned = Vec::new();
+
+    if let Some(worker) = status["workers"].get(0) {
+        if let Some(attributes) = worker["attributes"].as_object() {
+            learned.extend(attributes.keys().cloned());
+        }
+
+        if let Some(rules) = worker["rules"].as_array() {
+            learned.extend(rules.iter().filter_map(|rule| rule.as_str()).map(|rule| rule.to_string()));
+        }
+    }
+
+    learned.sort();
+    *names.borrow_mut() = learned;
+}
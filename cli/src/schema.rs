@@ -0,0 +1,145 @@
+//! Declarative schema files, listing attributes and their configs.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+
+use declarative_client::Client;
+use declarative_dataflow::server::CreateAttribute;
+use declarative_dataflow::Error;
+
+/// A schema entry, either plain or in the shape of a request.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Request {
+        #[serde(rename = "CreateAttribute")]
+        create_attribute: CreateAttribute,
+    },
+    Plain(CreateAttribute),
+}
+
+/// Reads a schema from a json file.
+pub fn from_file(path: &str) -> Result<Vec<CreateAttribute>, Error> {
+    let mut contents = String::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|error| Error::fault(format!("Failed to read schema: {}", error)))?;
+
+    parse(&contents)
+}
+
+/// Parses a schema from json.
+fn parse(contents: &str) -> Result<Vec<CreateAttribute>, Error> {
+    let entries: Vec<Entry> = serde_json::from_str(contents).map_err(Error::incorrect)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            Entry::Request { create_attribute } => create_attribute,
+            Entry::Plain(attribute) => attribute,
+        })
+        .collect())
+}
+
+/// Creates all attributes the server doesn't know yet. Nothing is
+/// created, if any attribute exists with a different configuration.
+pub fn apply(client: &mut Client, schema: Vec<CreateAttribute>, dry_run: bool) -> Result<(), Error> {
+    let existing: HashMap<String, CreateAttribute> = client
+        .schema()?
+        .into_iter()
+        .map(|attribute| (attribute.name.clone(), attribute))
+        .collect();
+
+    let mut missing = Vec::new();
+    let mut incompatible = Vec::new();
+
+    for attribute in schema.into_iter() {
+        match existing.get(&attribute.name) {
+            None => missing.push(attribute),
+            Some(current) => {
+                if current.config == attribute.config {
+                    println!("  {}", attribute.name);
+                } else {
+                    println!("! {} is {:?}, not {:?}", attribute.name, current.config, attribute.config);
+                    incompatible.push(attribute.name);
+                }
+            }
+        }
+    }
+
+    for attribute in missing.iter() {
+        println!("+ {}", attribute.name);
+    }
+
+    if !incompatible.is_empty() {
+        return Err(Error::conflict(format!(
+            "Refusing to change {}.",
+            incompatible.join(", ")
+        )));
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    for attribute in missing.into_iter() {
+        client.create_attribute(&attribute.name, attribute.config)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the server's current schema to the given file, or to
+/// stdout.
+pub fn dump(client: &mut Client, path: Option<&str>) -> Result<(), Error> {
+    let schema = client.schema()?;
+    let serialized = serde_json::to_string_pretty(&schema).map_err(Error::fault)?;
+
+    match path {
+        None => {
+            println!("{}", serialized);
+            Ok(())
+        }
+        Some(path) => File::create(path)
+            .and_then(|mut file| writeln!(file, "{}", serialized))
+            .map_err(|error| Error::fault(format!("Failed to write schema: {}", error))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_file, parse};
+    use declarative_dataflow::{AttributeConfig, InputSemantics};
+
+    #[test]
+    fn test_example_schema() {
+        let schema = from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/schema.json")).unwrap();
+        let names: Vec<&str> = schema.iter().map(|attribute| attribute.name.as_str()).collect();
+
+        assert_eq!(names, vec!["hero", "name", "age", "real"]);
+        assert_eq!(schema[0].config.input_semantics, InputSemantics::Distinct);
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = serde_json::to_value(AttributeConfig::tx_time(InputSemantics::Raw)).unwrap();
+        let contents = serde_json::json!([
+            {"CreateAttribute": {"name": ":name", "config": config.clone()}},
+            {"name": ":age", "config": config}
+        ]);
+
+        let schema = parse(&contents.to_string()).unwrap();
+
+        assert_eq!(schema.len(), 2);
+        assert_eq!(schema[0].name, ":name");
+        assert_eq!(schema[1].name, ":age");
+        assert_eq!(schema[1].config, AttributeConfig::tx_time(InputSemantics::Raw));
+
+        assert_eq!(
+            parse("[{\"name\": \":age\"}]").unwrap_err().category,
+            "df.error.category/incorrect"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Plain-text rendering of query results.
+
+use std::collections::BTreeMap;
+
+use declarative_dataflow::timestamp::Time;
+use declarative_dataflow::Value;
+
+/// Formats a single value for display.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Aid(aid) => aid.clone(),
+        Value::String(string) => string.clone(),
+        Value::Bool(boolean) => boolean.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Eid(eid) => eid.to_string(),
+        Value::Instant(instant) => instant.to_string(),
+        other => serde_json::to_string(other).expect("failed to serialize value"),
+    }
+}
+
+/// Formats a timestamp for display.
+pub fn format_time(time: &Time) -> String {
+    match time {
+        Time::TxId(tx) => tx.to_string(),
+        Time::Real(duration) => format!("{:?}", duration),
+        Time::Bi(duration, tx) => format!("{:?}/{}", duration, tx),
+    }
+}
+
+/// Renders rows as a table with aligned columns. Short rows are
+/// padded with empty cells.
+pub fn render(headers: &[String], rows: &[Vec<String>]) -> String {
+    let arity = rows.iter().map(|row| row.len()).fold(headers.len(), std::cmp::max);
+
+    let mut widths = vec![0; arity];
+    for row in std::iter::once(headers).chain(rows.iter().map(|row| row.as_slice())) {
+        for (index, cell) in row.iter().enumerate() {
+            widths[index] = std::cmp::max(widths[index], cell.chars().count());
+        }
+    }
+
+    let format_row = |row: &[String]| {
+        let cells: Vec<String> = (0..arity)
+            .map(|index| {
+                let cell = row.get(index).map(|cell| cell.as_str()).unwrap_or("");
+                format!("{:width$}", cell, width = widths[index])
+            })
+            .collect();
+
+        cells.join(" | ").trim_end().to_string()
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 2);
+
+    if !headers.is_empty() {
+        lines.push(format_row(headers));
+        lines.push(
+            widths
+                .iter()
+                .map(|width| "-".repeat(*width))
+                .collect::<Vec<String>>()
+                .join("-+-"),
+        );
+    }
+
+    for row in rows.iter() {
+        lines.push(format_row(row));
+    }
+
+    lines.join("\n")
+}
+
+/// Renders a consolidated relation, with a column for the count of
+/// each tuple, if any of them appears more than once.
+pub fn render_relation(relation: &BTreeMap<Vec<Value>, isize>) -> String {
+    if relation.is_empty() {
+        return "(empty)".to_string();
+    }
+
+    let with_counts = relation.values().any(|count| *count != 1);
+    let arity = relation.keys().map(|tuple| tuple.len()).max().unwrap_or(0);
+
+    let mut headers: Vec<String> = (0..arity).map(|index| format!("?{}", index)).collect();
+    if with_counts {
+        headers.push("#".to_string());
+    }
+
+    let rows: Vec<Vec<String>> = relation
+        .iter()
+        .map(|(tuple, count)| {
+            let mut row: Vec<String> = tuple.iter().map(format_value).collect();
+            if with_counts {
+                row.resize(arity, String::new());
+                row.push(count.to_string());
+            }
+            row
+        })
+        .collect();
+
+    render(&headers, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_time, render, render_relation};
+    use declarative_dataflow::timestamp::Time;
+    use declarative_dataflow::Value;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let headers = vec!["name".to_string(), "age".to_string()];
+        let rows = vec![
+            vec!["Mabel".to_string(), "12".to_string()],
+            vec!["Grunkle Stan".to_string()],
+        ];
+
+        assert_eq!(
+            render(&headers, &rows),
+            "name         | age\n-------------+----\nMabel        | 12\nGrunkle Stan |"
+        );
+
+        assert_eq!(render(&[], &rows[..1]), "Mabel | 12");
+    }
+
+    #[test]
+    fn test_render_relation() {
+        let mut relation = BTreeMap::new();
+        assert_eq!(render_relation(&relation), "(empty)");
+
+        relation.insert(vec![Value::Eid(1), Value::String("Mabel".to_string())], 1);
+        assert_eq!(render_relation(&relation), "?0 | ?1\n---+------\n1  | Mabel");
+
+        relation.insert(vec![Value::Eid(2)], 2);
+        assert_eq!(
+            render_relation(&relation),
+            "?0 | ?1    | #\n---+-------+--\n1  | Mabel | 1\n2  |       | 2"
+        );
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(&Time::TxId(3)), "3");
+        assert_eq!(format_time(&Time::Bi(Duration::from_millis(5), 3)), "5ms/3");
+    }
+}
@@ -0,0 +1,896 @@
+//! A typed client for Declarative Dataflow servers.
+//!
+//! The client keeps a single websocket connection open in the
+//! background and transparently re-establishes it, should it drop.
+//! Authentication and all active interests are restored on each
+//! reconnect. Interests are resumed from the last time up to which
+//! all results had been received, falling back to a fresh snapshot
+//! if the server can't tell what happened since.
+//!
+//! Requests are sent in batches. Once a batch has been handled, the
+//! server tells us the transaction id it assigned to the batch.
+//! Errors caused by the batch carry the same id and arrive before
+//! that, which is how we attribute them. Batches containing a
+//! `Transact` are terminated by a `TransactReport`, whose report
+//! tells us the time at which their inputs were inserted. Errors
+//! carrying any other id (e.g. constraint violations, or evictions
+//! caused by other clients) are forwarded via `Client::errors`.
+//!
+//! Interests are served from the server's shared results. Those
+//! can't be resumed, so after a reconnect they are resumed from a
+//! subscription of their own.
+
+#![forbid(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ws::{CloseCode, Handler, Handshake, Message};
+
+use declarative_dataflow::server::{CreateAttribute, Interest, Register, Request, TxId};
+use declarative_dataflow::timestamp::Time;
+use declarative_dataflow::{Aid, AttributeConfig, Datom, Error, Output, ResultDiff, Rule, Value};
+
+/// Initial delay between reconnection attempts.
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Maximum delay between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Category of the message marking a handled batch.
+const HANDLED: &str = "df/handled";
+
+/// Message of errors caused by requests issued while disconnected.
+const NOT_CONNECTED: &str = "Not connected.";
+
+/// Prefix of errors caused by requests that could not be sent.
+const NOT_SENT: &str = "Failed to send:";
+
+/// Message of errors caused by the connection dropping while a
+/// request was pending.
+const CONNECTION_LOST: &str = "Connection lost.";
+
+/// Message of errors caused by the server not answering in time.
+const TIMED_OUT: &str = "Timed out waiting for the server.";
+
+/// Returns true iff the error means that a request never reached the
+/// server, s.t. it can safely be retried.
+pub fn is_unsent(error: &Error) -> bool {
+    error.category == "df.error.category/fault"
+        && (error.message == NOT_CONNECTED || error.message.starts_with(NOT_SENT))
+}
+
+/// Returns true iff the error means that a request was sent, but the
+/// server never answered it, s.t. there is no telling whether it was
+/// applied.
+pub fn is_unanswered(error: &Error) -> bool {
+    error.category == "df.error.category/fault"
+        && (error.message == CONNECTION_LOST || error.message == TIMED_OUT)
+}
+
+/// What the server told us about a successfully applied batch of
+/// requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Receipt {
+    /// The transaction id assigned to the batch.
+    pub tx: TxId,
+    /// The time at which the batch's inputs were inserted, or at
+    /// which it was handled, if it had no inputs.
+    pub time: Time,
+}
+
+/// The results of a relation as of a specific time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// Results include all updates before this time.
+    pub time: Time,
+    /// Consolidated results, all stamped with the snapshot's time.
+    pub results: Vec<ResultDiff<Time>>,
+}
+
+/// A stream of result diffs for a single interest. The stream ends
+/// once the interest is withdrawn or the client is dropped.
+pub struct Subscription {
+    name: String,
+    recv: Receiver<Vec<ResultDiff<Time>>>,
+}
+
+impl Subscription {
+    /// The name this subscription is interested in.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Waits at most for the specified duration for the next batch
+    /// of diffs.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<Vec<ResultDiff<Time>>> {
+        self.recv.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next batch of diffs, if one is available already.
+    pub fn try_next(&self) -> Option<Vec<ResultDiff<Time>>> {
+        self.recv.try_recv().ok()
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Vec<ResultDiff<Time>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.recv().ok()
+    }
+}
+
+/// Client-side state of an interest.
+struct Interested {
+    /// The request to replay on reconnect.
+    interest: Interest,
+    /// Where to forward results.
+    send: Sender<Vec<ResultDiff<Time>>>,
+    /// All results forwarded so far, consolidated.
+    snapshot: BTreeMap<Vec<Value>, isize>,
+    /// The latest time seen in any result.
+    last_time: Option<Time>,
+    /// The time up to which all results have been received.
+    complete: Option<Time>,
+    /// Results received at times beyond `complete`.
+    pending: Vec<ResultDiff<Time>>,
+}
+
+impl Interested {
+    /// Applies results to the snapshot and forwards them.
+    fn forward(&mut self, results: Vec<ResultDiff<Time>>) {
+        for (tuple, time, diff) in results.iter() {
+            let count = {
+                let count = self.snapshot.entry(tuple.clone()).or_insert(0);
+                *count += diff;
+                *count
+            };
+
+            if count == 0 {
+                self.snapshot.remove(tuple);
+            }
+
+            self.last_time = Some(time.clone());
+        }
+
+        if !results.is_empty() {
+            self.send.send(results).ok();
+        }
+    }
+
+    /// Retracts everything forwarded so far and starts over from a
+    /// fresh snapshot of the shared results.
+    fn reset(&mut self) {
+        if let Some(time) = self.last_time.clone() {
+            let retractions = self
+                .snapshot
+                .iter()
+                .map(|(tuple, count)| (tuple.clone(), time.clone(), -count))
+                .collect();
+
+            self.forward(retractions);
+        }
+
+        self.complete = None;
+        self.pending.clear();
+        self.interest.id = None;
+        self.interest.resume_from = None;
+    }
+}
+
+/// Who is waiting for a batch to be handled.
+enum Waiter {
+    /// The batch was issued by the client itself.
+    Restore,
+    /// The batch resumes the named interest.
+    Resume(String),
+    /// A caller is waiting.
+    Caller(Sender<Result<Receipt, Error>>),
+}
+
+/// State shared between the client and its connection.
+struct State {
+    /// Sender for the current connection, if any.
+    out: Option<ws::Sender>,
+    /// Signals the outcome of the initial connection attempt.
+    opened: Option<Sender<Result<(), Error>>>,
+    /// Set once the client is dropped.
+    closing: bool,
+    /// Secret to authenticate with on every connect.
+    secret: Option<String>,
+    /// Batches waiting to be handled, in the order they were
+    /// sent. Batches issued by the client itself have no waiter.
+    batches: VecDeque<Waiter>,
+    /// Errors received while batches are pending, by transaction id.
+    batch_errors: BTreeMap<TxId, Vec<Error>>,
+    /// Times reported while batches are pending, by transaction id.
+    batch_times: BTreeMap<TxId, Time>,
+    /// The latest transaction id handled on this connection.
+    last_acked: Option<TxId>,
+    /// Requests waiting for a message, by message category.
+    messages: HashMap<String, VecDeque<Sender<serde_json::Value>>>,
+    /// Queries waiting for their snapshot, by name.
+    snapshots: HashMap<String, VecDeque<Sender<Snapshot>>>,
+    /// Active interests by name.
+    interests: HashMap<String, Interested>,
+    /// Distinguishes our subscription ids from other clients' ones.
+    session: String,
+    /// Sequence number of the next subscription id.
+    next_id: usize,
+    /// Where to forward errors that no batch is waiting for.
+    errors: Sender<(TxId, Error)>,
+}
+
+impl State {
+    /// Sends a batch of requests. Batches with transactions are
+    /// terminated by a report.
+    fn submit(
+        &mut self,
+        mut requests: Vec<Request<Aid>>,
+        waiter: Waiter,
+    ) -> Result<(), Error> {
+        let is_transaction = requests.iter().any(|req| match req {
+            Request::Transact(_) => true,
+            _ => false,
+        });
+
+        let is_reported = match requests.last() {
+            Some(Request::TransactReport(_)) => true,
+            _ => false,
+        };
+
+        if is_transaction && !is_reported {
+            requests.push(Request::TransactReport(Vec::new()));
+        }
+
+        let serialized = serde_json::to_string(&requests).map_err(Error::incorrect)?;
+
+        self.send(serialized, waiter)
+    }
+
+    /// Authenticates the connection with the configured secret, if
+    /// any. Authentication is a message of its own, but handled just
+    /// like a batch.
+    fn authenticate(&mut self, waiter: Waiter) -> Result<(), Error> {
+        let message = match self.secret {
+            None => return Err(Error::incorrect("No secret to authenticate with.")),
+            Some(ref secret) => serde_json::json!({ "Authenticate": secret }).to_string(),
+        };
+
+        self.send(message, waiter)
+    }
+
+    /// Sends a message that will be handled like a batch.
+    fn send(&mut self, message: String, waiter: Waiter) -> Result<(), Error> {
+        match self.out {
+            None => Err(Error::fault(NOT_CONNECTED)),
+            Some(ref out) => {
+                out.send(message)
+                    .map_err(|error| Error::fault(format!("{} {}", NOT_SENT, error)))?;
+
+                self.batches.push_back(waiter);
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Resolves the oldest batch, which the server has handled under
+    /// the given transaction id.
+    fn resolve(&mut self, tx: TxId, time: Time) {
+        let errors = self.batch_errors.remove(&tx).unwrap_or_default();
+        let time = self.batch_times.remove(&tx).unwrap_or(time);
+
+        // Anything still left from earlier transactions wasn't caused
+        // by any of our batches.
+        let later_errors = self.batch_errors.split_off(&(tx + 1));
+        for (other, errors) in std::mem::replace(&mut self.batch_errors, later_errors) {
+            for error in errors.into_iter() {
+                self.errors.send((other, error)).ok();
+            }
+        }
+
+        self.batch_times = self.batch_times.split_off(&(tx + 1));
+
+        match self.batches.pop_front() {
+            None => warn!("A batch was handled without being pending."),
+            Some(Waiter::Restore) => {
+                for error in errors.into_iter() {
+                    error!("Failed to restore connection state: {:?}", error);
+                }
+            }
+            Some(Waiter::Resume(name)) => {
+                let error = match errors.into_iter().next() {
+                    None => return,
+                    Some(error) => error,
+                };
+
+                if error.category != "df.error.category/snapshot-required" {
+                    error!("Failed to resume {}: {:?}", name, error);
+                    return;
+                }
+
+                info!("Resuming {} requires a snapshot.", name);
+
+                let interest = match self.interests.get_mut(&name) {
+                    None => return,
+                    Some(interested) => {
+                        interested.reset();
+                        interested.interest.clone()
+                    }
+                };
+
+                if let Err(error) = self.submit(vec![Request::Interest(interest)], Waiter::Restore) {
+                    error!("Failed to restore connection state: {:?}", error);
+                }
+            }
+            Some(Waiter::Caller(waiter)) => {
+                let result = match errors.into_iter().next() {
+                    None => Ok(Receipt { tx, time }),
+                    Some(error) => Err(error),
+                };
+
+                waiter.send(result).ok();
+            }
+        }
+    }
+
+    /// Called once a connection has been established.
+    fn open(&mut self, out: ws::Sender) {
+        self.out = Some(out);
+        self.last_acked = None;
+
+        // The server only tells us about handled batches if asked
+        // to. Connection requests are acknowledged like batches, so
+        // this one is, too.
+        let acknowledge = serde_json::json!("AcknowledgeBatches").to_string();
+        if let Err(error) = self.send(acknowledge, Waiter::Restore) {
+            error!("Failed to restore connection state: {:?}", error);
+        }
+
+        if self.secret.is_some() {
+            if let Err(error) = self.authenticate(Waiter::Restore) {
+                error!("Failed to restore connection state: {:?}", error);
+            }
+        }
+
+        let mut requests = Vec::new();
+        let mut resumed = Vec::new();
+
+        for (name, interested) in self.interests.iter() {
+            if interested.interest.resume_from.is_some() {
+                resumed.push((name.clone(), interested.interest.clone()));
+            } else {
+                requests.push(Request::Interest(interested.interest.clone()));
+            }
+        }
+
+        if let Err(error) = self.submit(requests, Waiter::Restore) {
+            error!("Failed to restore connection state: {:?}", error);
+        }
+
+        // Each resumed interest gets a batch of its own, s.t. we know
+        // which one to start over, if necessary.
+        for (name, interest) in resumed.into_iter() {
+            if let Err(error) = self.submit(vec![Request::Interest(interest)], Waiter::Resume(name)) {
+                error!("Failed to restore connection state: {:?}", error);
+            }
+        }
+
+        if let Some(opened) = self.opened.take() {
+            opened.send(Ok(())).ok();
+        }
+    }
+
+    /// Called once the connection is gone.
+    fn close(&mut self) {
+        self.out = None;
+        self.batch_errors.clear();
+        self.batch_times.clear();
+
+        for waiter in self.batches.drain(..) {
+            if let Waiter::Caller(waiter) = waiter {
+                waiter.send(Err(Error::fault(CONNECTION_LOST))).ok();
+            }
+        }
+
+        self.messages.clear();
+        self.snapshots.clear();
+
+        // The server will resend all results beyond the last complete
+        // time once we are back, so those have to be retracted. If
+        // nothing is complete yet, we start over entirely.
+        for interested in self.interests.values_mut() {
+            match interested.complete.clone() {
+                None => interested.reset(),
+                Some(complete) => {
+                    let retractions = interested
+                        .pending
+                        .drain(..)
+                        .map(|(tuple, time, diff)| (tuple, time, -diff))
+                        .collect();
+
+                    interested.forward(retractions);
+
+                    // Shared results can't be resumed, so we resume
+                    // from a subscription of our own.
+                    if interested.interest.id.is_none() {
+                        let id = format!("{}@{}.{}", interested.interest.name, self.session, self.next_id);
+                        interested.interest.id = Some(id);
+                        self.next_id += 1;
+                    }
+
+                    interested.interest.resume_from = Some(complete);
+                }
+            }
+        }
+    }
+
+    /// Returns the interest whose results are published under the
+    /// given subscription id.
+    fn subscription(&mut self, id: &str) -> Option<&mut Interested> {
+        self.interests
+            .values_mut()
+            .find(|interested| interested.interest.key() == id)
+    }
+
+    /// Routes an output from the server.
+    fn handle(&mut self, out: Output) {
+        match out {
+            Output::TxReport(_, tx, time) => {
+                self.batch_times.insert(tx, time);
+            }
+            Output::Message(_, ref message) if message["category"] == HANDLED => {
+                let handled = serde_json::from_value::<TxId>(message["tx"].clone()).and_then(|tx| {
+                    serde_json::from_value::<Time>(message["time"].clone()).map(|time| (tx, time))
+                });
+
+                match handled {
+                    Err(error) => error!("Failed to parse {}: {}", message, error),
+                    Ok((tx, time)) => {
+                        self.last_acked = Some(tx);
+                        self.resolve(tx, time);
+                    }
+                }
+            }
+            Output::Error(_, error, tx) => {
+                let is_late = self.last_acked.map(|acked| tx <= acked).unwrap_or(false);
+
+                if is_late || self.batches.is_empty() {
+                    self.errors.send((tx, error)).ok();
+                } else {
+                    self.batch_errors.entry(tx).or_insert_with(Vec::new).push(error);
+                }
+            }
+            Output::QueryDiff(id, results) => match self.subscription(&id) {
+                None => trace!("Ignoring results for {}.", id),
+                Some(interested) => {
+                    interested.pending.extend(results.iter().cloned());
+                    interested.forward(results);
+                }
+            },
+            Output::Complete(id, time) => {
+                if let Some(interested) = self.subscription(&id) {
+                    interested.pending.retain(|(_tuple, t, _diff)| t > &time);
+                    interested.complete = Some(time);
+                }
+            }
+            Output::Snapshot(_, name, time, results) => {
+                match self.snapshots.get_mut(&name).and_then(|waiters| waiters.pop_front()) {
+                    None => trace!("Ignoring snapshot of {}.", name),
+                    Some(waiter) => {
+                        waiter.send(Snapshot { time, results }).ok();
+                    }
+                }
+            }
+            Output::Message(_, message) => {
+                let waiter = message["category"]
+                    .as_str()
+                    .and_then(|category| self.messages.get_mut(category))
+                    .and_then(|waiters| waiters.pop_front());
+
+                match waiter {
+                    None => info!("{}", message),
+                    Some(waiter) => {
+                        waiter.send(message).ok();
+                    }
+                }
+            }
+            other => trace!("Ignoring {:?}", other),
+        }
+    }
+}
+
+/// A single websocket connection.
+struct Connection {
+    out: ws::Sender,
+    state: Arc<Mutex<State>>,
+}
+
+impl Handler for Connection {
+    fn on_open(&mut self, _shake: Handshake) -> ws::Result<()> {
+        info!("Connected.");
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.closing {
+            self.out.close(CloseCode::Normal)
+        } else {
+            state.open(self.out.clone());
+            Ok(())
+        }
+    }
+
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        let parsed = match msg {
+            Message::Text(text) => serde_json::from_str::<Output>(&text).map_err(Error::incorrect),
+            Message::Binary(_) => Err(Error::unsupported("Binary messages are not supported.")),
+        };
+
+        match parsed {
+            Err(error) => error!("Failed to parse output: {:?}", error),
+            Ok(out) => self.state.lock().unwrap().handle(out),
+        }
+
+        Ok(())
+    }
+
+    fn on_close(&mut self, code: CloseCode, reason: &str) {
+        info!("Connection closed ({:?}, {}).", code, reason);
+    }
+
+    fn on_error(&mut self, error: ws::Error) {
+        warn!("Connection error: {}", error);
+    }
+}
+
+/// A connection to a Declarative Dataflow server.
+pub struct Client {
+    state: Arc<Mutex<State>>,
+    errors: Receiver<(TxId, Error)>,
+    timeout: Duration,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Client {
+    /// Connects to the server at the given websocket url,
+    /// e.g. `ws://127.0.0.1:6262`. Fails if the server can't be
+    /// reached initially. Later disconnects are handled
+    /// transparently.
+    pub fn connect(url: &str) -> Result<Self, Error> {
+        let (send_errors, errors) = channel();
+        let (send_opened, opened) = channel();
+
+        let state = Arc::new(Mutex::new(State {
+            out: None,
+            opened: Some(send_opened),
+            closing: false,
+            secret: None,
+            batches: VecDeque::new(),
+            batch_errors: BTreeMap::new(),
+            batch_times: BTreeMap::new(),
+            last_acked: None,
+            messages: HashMap::new(),
+            snapshots: HashMap::new(),
+            interests: HashMap::new(),
+            session: session_id(),
+            next_id: 0,
+            errors: send_errors,
+        }));
+
+        let thread = {
+            let state = state.clone();
+            let url = url.to_string();
+
+            thread::spawn(move || {
+                let mut backoff = MIN_BACKOFF;
+
+                loop {
+                    let result = ws::connect(url.as_str(), |out| Connection {
+                        out,
+                        state: state.clone(),
+                    });
+
+                    let mut state = state.lock().unwrap();
+
+                    if state.out.is_some() {
+                        backoff = MIN_BACKOFF;
+                    }
+
+                    state.close();
+
+                    if let Some(opened) = state.opened.take() {
+                        let error = match result {
+                            Err(error) => Error::fault(format!("Failed to connect: {}", error)),
+                            Ok(()) => Error::fault("Failed to connect."),
+                        };
+
+                        opened.send(Err(error)).ok();
+                        return;
+                    }
+
+                    if state.closing {
+                        return;
+                    }
+
+                    drop(state);
+
+                    warn!("Disconnected, retrying in {:?}.", backoff);
+                    thread::sleep(backoff);
+
+                    backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                }
+            })
+        };
+
+        let client = Client {
+            state,
+            errors,
+            timeout: Duration::from_secs(30),
+            thread: Some(thread),
+        };
+
+        match opened.recv() {
+            Ok(Ok(())) => Ok(client),
+            Ok(Err(error)) => Err(error),
+            Err(_) => Err(Error::fault("Failed to connect.")),
+        }
+    }
+
+    /// Sets how long to wait for responses from the server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long to wait for responses from the server.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Errors that could not be attributed to a pending request,
+    /// e.g. transactions rejected by a constraint after they were
+    /// handled. Each error comes with the id of the transaction
+    /// that caused it.
+    pub fn errors(&self) -> &Receiver<(TxId, Error)> {
+        &self.errors
+    }
+
+    /// Sends a batch of requests and waits for it to be applied.
+    /// Fails with the first error caused by any of the requests.
+    pub fn request(&mut self, requests: Vec<Request<Aid>>) -> Result<Receipt, Error> {
+        let (send, recv) = channel();
+
+        self.state.lock().unwrap().submit(requests, Waiter::Caller(send))?;
+
+        self.wait(&recv)
+    }
+
+    /// Authenticates this client, now and on every reconnect.
+    pub fn authenticate(&mut self, secret: &str) -> Result<Receipt, Error> {
+        let (send, recv) = channel();
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.secret = Some(secret.to_string());
+            state.authenticate(Waiter::Caller(send))?;
+        }
+
+        self.wait(&recv)
+    }
+
+    /// Transacts the given datoms. The receipt tells the
+    /// transaction's id and the time its inputs were inserted at.
+    pub fn transact(&mut self, tx_data: Vec<Datom<Aid>>) -> Result<Receipt, Error> {
+        self.request(vec![Request::TransactReport(tx_data)])
+    }
+
+    /// Creates a new attribute.
+    pub fn create_attribute(&mut self, name: &str, config: AttributeConfig) -> Result<Receipt, Error> {
+        self.request(vec![Request::CreateAttribute(CreateAttribute {
+            name: name.to_string(),
+            config,
+        })])
+    }
+
+    /// Registers the given rules, publishing the specified ones.
+    pub fn register(&mut self, rules: Vec<Rule<Aid>>, publish: Vec<Aid>) -> Result<Receipt, Error> {
+        self.request(vec![Request::Register(Register {
+            rules,
+            publish,
+            replace: None,
+        })])
+    }
+
+    /// Expresses interest in the named relation and returns a stream
+    /// of its results. Across reconnects, the stream stays
+    /// consistent: results the server will send anew are retracted
+    /// first. Usually these are only the ones beyond the last
+    /// complete time, but subscriptions that can't be resumed start
+    /// over entirely.
+    pub fn interest(&mut self, name: &str) -> Result<Subscription, Error> {
+        let (send, recv) = channel();
+
+        let (send_receipt, receipt) = channel();
+
+        {
+            let mut state = self.state.lock().unwrap();
+
+            if state.interests.contains_key(name) {
+                return Err(Error::conflict(format!("Already interested in {}.", name)));
+            }
+
+            let interest = Interest {
+                name: name.to_string(),
+                granularity: None,
+                sink: None,
+                disable_logging: None,
+                id: None,
+                resume_from: None,
+            };
+
+            state.submit(vec![Request::Interest(interest.clone())], Waiter::Caller(send_receipt))?;
+            state.interests.insert(
+                name.to_string(),
+                Interested {
+                    interest,
+                    send,
+                    snapshot: BTreeMap::new(),
+                    last_time: None,
+                    complete: None,
+                    pending: Vec::new(),
+                },
+            );
+        }
+
+        match self.wait(&receipt) {
+            Err(error) => {
+                self.state.lock().unwrap().interests.remove(name);
+                Err(error)
+            }
+            Ok(_receipt) => Ok(Subscription {
+                name: name.to_string(),
+                recv,
+            }),
+        }
+    }
+
+    /// Withdraws interest in the named relation, ending its
+    /// subscription.
+    pub fn uninterest(&mut self, name: &str) -> Result<Receipt, Error> {
+        let interested = self.state.lock().unwrap().interests.remove(name);
+
+        match interested {
+            None => Err(Error::not_found(format!("Not interested in {}.", name))),
+            Some(interested) => {
+                let id = interested.interest.key().to_string();
+                self.request(vec![Request::Uninterest(id)])
+            }
+        }
+    }
+
+    /// Retrieves the current results of the named relation once,
+    /// without subscribing to it.
+    pub fn query(&mut self, name: &str) -> Result<Snapshot, Error> {
+        let recv = self.request_snapshot(name)?;
+
+        match recv.recv_timeout(self.timeout) {
+            Ok(snapshot) => Ok(snapshot),
+            Err(RecvTimeoutError::Timeout) => Err(Error::fault("Timed out waiting for snapshot.")),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::fault(CONNECTION_LOST)),
+        }
+    }
+
+    /// Requests the current results of the named relation once,
+    /// without waiting for them to arrive. The snapshot is sent to
+    /// the returned receiver, which is dropped if the connection is
+    /// lost first.
+    pub fn request_snapshot(&mut self, name: &str) -> Result<Receiver<Snapshot>, Error> {
+        let (send, recv) = channel();
+
+        self.state
+            .lock()
+            .unwrap()
+            .snapshots
+            .entry(name.to_string())
+            .or_insert_with(VecDeque::new)
+            .push_back(send);
+
+        let request = Request::Query {
+            name: name.to_string(),
+            as_of: None,
+        };
+
+        if let Err(error) = self.request(vec![request]) {
+            if let Some(waiters) = self.state.lock().unwrap().snapshots.get_mut(name) {
+                waiters.pop_back();
+            }
+
+            return Err(error);
+        }
+
+        Ok(recv)
+    }
+
+    /// Asks all workers for their status.
+    pub fn status(&mut self) -> Result<serde_json::Value, Error> {
+        self.request_message(Request::Status, "df/status")
+    }
+
+    /// Retrieves all attributes and their configurations.
+    pub fn schema(&mut self) -> Result<Vec<CreateAttribute>, Error> {
+        let mut schema = self.request_message(Request::Schema, "df/schema")?;
+
+        serde_json::from_value(schema["attributes"].take()).map_err(Error::incorrect)
+    }
+
+    /// Sends a request and waits for the message of the given
+    /// category it is answered with.
+    fn request_message(&mut self, request: Request<Aid>, category: &str) -> Result<serde_json::Value, Error> {
+        let (send, recv) = channel();
+
+        self.state
+            .lock()
+            .unwrap()
+            .messages
+            .entry(category.to_string())
+            .or_insert_with(VecDeque::new)
+            .push_back(send);
+
+        if let Err(error) = self.request(vec![request]) {
+            if let Some(waiters) = self.state.lock().unwrap().messages.get_mut(category) {
+                waiters.pop_back();
+            }
+
+            return Err(error);
+        }
+
+        match recv.recv_timeout(self.timeout) {
+            Ok(message) => Ok(message),
+            Err(RecvTimeoutError::Timeout) => Err(Error::fault(format!("Timed out waiting for {}.", category))),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::fault(CONNECTION_LOST)),
+        }
+    }
+
+    fn wait(&self, recv: &Receiver<Result<Receipt, Error>>) -> Result<Receipt, Error> {
+        match recv.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(Error::fault(TIMED_OUT)),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::fault(CONNECTION_LOST)),
+        }
+    }
+}
+
+/// Derives a prefix for subscription ids, unique to this client.
+fn session_id() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    format!("{}.{}.{}", std::process::id(), since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.closing = true;
+            state.interests.clear();
+
+            if let Some(ref out) = state.out {
+                out.close(CloseCode::Normal).ok();
+            }
+        }
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use declarative_client::{Client, Subscription};
+use declarative_dataflow::server::{CreateAttribute, Request};
+use declarative_dataflow::timestamp::Time;
+use declarative_dataflow::{Aid, AttributeConfig, Datom, Error, InputSemantics, Output, Value};
+
+/// A stand-in for a real server, answering requests the way the real
+/// one does, without running any dataflows. Every batch is marked as
+/// handled, as are connection requests, which are otherwise
+/// ignored. `Tick` requests make it drop the connection. `Shutdown`
+/// requests stand in for another client's batch, sequenced just
+/// before, that causes an error for this client.
+/// Subscriptions can only be resumed, if the server remembers their
+/// history. Returns the url it is listening on, accepting
+/// connections right away.
+fn serve(remembers: bool) -> String {
+    let next_tx = Arc::new(AtomicUsize::new(0));
+    let attributes = Arc::new(Mutex::new(HashSet::new()));
+
+    let socket = ws::WebSocket::new(move |out: ws::Sender| {
+        let next_tx = next_tx.clone();
+        let attributes = attributes.clone();
+
+        move |msg: ws::Message| {
+            let requests: Vec<Request<Aid>> = serde_json::from_str(msg.as_text()?).unwrap_or_default();
+
+            let send = |output: Output| out.send(serde_json::to_string(&output).unwrap());
+
+            let is_evicted = requests.iter().any(|req| match req {
+                Request::Shutdown => true,
+                _ => false,
+            });
+
+            if is_evicted {
+                let other = next_tx.fetch_add(1, Ordering::SeqCst) as u64;
+                send(Output::Error(0, Error::not_found("Evicted."), other))?;
+            }
+
+            let tx = next_tx.fetch_add(1, Ordering::SeqCst) as u64;
+
+            for req in requests.into_iter() {
+                match req {
+                    Request::CreateAttribute(CreateAttribute { name, .. }) => {
+                        if !attributes.lock().unwrap().insert(name.clone()) {
+                            let error = Error::conflict(format!("{} exists.", name));
+                            send(Output::Error(0, error, tx))?;
+                        }
+                    }
+                    Request::Interest(interest) => {
+                        let key = interest.key().to_string();
+
+                        let results = match interest.resume_from {
+                            None => vec![(vec![Value::Number(1)], Time::TxId(tx), 1)],
+                            Some(_) if remembers => vec![(vec![Value::Number(2)], Time::TxId(tx), 1)],
+                            Some(_) => {
+                                let error = Error::snapshot_required(format!("{} is gone.", key));
+                                send(Output::Error(0, error, tx))?;
+                                continue;
+                            }
+                        };
+
+                        send(Output::QueryDiff(key.clone(), results))?;
+                        send(Output::Complete(key, Time::TxId(tx)))?;
+                    }
+                    Request::Query { name, .. } => {
+                        let results = vec![(vec![Value::Number(1)], Time::TxId(tx), 1)];
+                        send(Output::Snapshot(0, name, Time::TxId(tx), results))?;
+                    }
+                    Request::TransactReport(_) => {
+                        send(Output::TxReport(0, tx, Time::TxId(tx)))?;
+                    }
+                    Request::Status => {
+                        let status = serde_json::json!({"category": "df/status", "workers": []});
+                        send(Output::Message(0, status))?;
+                    }
+                    Request::Tick => {
+                        return out.close(ws::CloseCode::Away);
+                    }
+                    _ => {}
+                }
+            }
+
+            let handled = serde_json::json!({"category": "df/handled", "tx": tx, "time": Time::TxId(tx)});
+            send(Output::Message(0, handled))
+        }
+    })
+    .and_then(|socket| socket.bind("127.0.0.1:0"))
+    .expect("failed to bind mock server");
+
+    let address = socket.local_addr().expect("failed to get mock server address");
+
+    thread::spawn(move || socket.run().expect("mock server failed"));
+
+    format!("ws://{}", address)
+}
+
+/// Returns the next batch of results without their times.
+fn next_diffs(subscription: &Subscription) -> Vec<(Vec<Value>, isize)> {
+    subscription
+        .next_timeout(Duration::from_secs(5))
+        .expect("no results")
+        .into_iter()
+        .map(|(tuple, _time, diff)| (tuple, diff))
+        .collect()
+}
+
+#[test]
+fn correlate_errors() {
+    let mut client = Client::connect(&serve(true))
+        .unwrap()
+        .with_timeout(Duration::from_secs(5));
+
+    let config = AttributeConfig::tx_time(InputSemantics::Raw);
+    let created = client.create_attribute(":name", config.clone()).unwrap();
+
+    assert_eq!(
+        client.create_attribute(":name", config).unwrap_err().category,
+        "df.error.category/conflict"
+    );
+
+    // Each batch gets a transaction id of its own.
+    let receipt = client
+        .transact(vec![Datom::add(1, ":name", Value::String("Dipper".to_string()))])
+        .unwrap();
+
+    assert!(receipt.tx > created.tx + 1);
+    assert_eq!(receipt.time, Time::TxId(receipt.tx));
+
+    assert_eq!(client.status().unwrap()["category"], "df/status");
+
+    let snapshot = client.query("names").unwrap();
+    assert!(snapshot.time > receipt.time);
+    assert_eq!(
+        snapshot.results,
+        vec![(vec![Value::Number(1)], snapshot.time.clone(), 1)]
+    );
+}
+
+#[test]
+fn forward_errors_of_other_transactions() {
+    let mut client = Client::connect(&serve(true))
+        .unwrap()
+        .with_timeout(Duration::from_secs(5));
+
+    // The error was caused by another transaction, so our batch
+    // still succeeds.
+    let receipt = client.request(vec![Request::Shutdown]).unwrap();
+
+    let (tx, error) = client
+        .errors()
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+
+    assert!(tx < receipt.tx);
+    assert_eq!(error.category, "df.error.category/not-found");
+}
+
+#[test]
+fn reconnect() {
+    let mut client = Client::connect(&serve(false))
+        .unwrap()
+        .with_timeout(Duration::from_secs(5));
+
+    let mut subscription = client.interest("names").unwrap();
+
+    assert_eq!(next_diffs(&subscription), vec![(vec![Value::Number(1)], 1)]);
+
+    // The server goes away before answering.
+    assert!(client.request(vec![Request::Tick]).is_err());
+
+    // Resuming fails, so everything seen before is retracted and
+    // then replayed.
+    assert_eq!(next_diffs(&subscription), vec![(vec![Value::Number(1)], -1)]);
+    assert_eq!(next_diffs(&subscription), vec![(vec![Value::Number(1)], 1)]);
+
+    client.uninterest("names").unwrap();
+    assert_eq!(subscription.next(), None);
+}
+
+#[test]
+fn resume() {
+    let mut client = Client::connect(&serve(true))
+        .unwrap()
+        .with_timeout(Duration::from_secs(5));
+
+    let subscription = client.interest("names").unwrap();
+
+    assert_eq!(next_diffs(&subscription), vec![(vec![Value::Number(1)], 1)]);
+
+    assert!(client.request(vec![Request::Tick]).is_err());
+
+    // Only what happened since the last complete time is sent.
+    assert_eq!(next_diffs(&subscription), vec![(vec![Value::Number(2)], 1)]);
+}
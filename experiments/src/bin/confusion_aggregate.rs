@@ -56,6 +56,7 @@ fn main() {
                 Register {
                     rules,
                     publish: vec!["q2".to_string()],
+                    replace: None,
                 },
                 scope,
             );
@@ -54,6 +54,7 @@ fn main() {
                 Register {
                     rules,
                     publish: vec!["q1".to_string()],
+                    replace: None,
                 },
                 scope,
             );
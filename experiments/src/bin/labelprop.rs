@@ -76,6 +76,7 @@ fn main() {
                 .register(Register {
                     rules,
                     publish: vec!["labelprop".to_string()],
+                    replace: None,
                 })
                 .unwrap();
 
@@ -0,0 +1,230 @@
+//! Authentication and access control for client connections.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+
+use declarative_dataflow::plan::Implementable;
+use declarative_dataflow::server::{CreateAttribute, Interest, Register, Request};
+use declarative_dataflow::sinks::Sink;
+use declarative_dataflow::sources::Source;
+use declarative_dataflow::{Datom, Error};
+
+use crate::Aid;
+
+/// What an authenticated client is allowed to do. Names may end in
+/// a `*`, in which case they match any name with that prefix.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Permissions {
+    /// Request types the client may issue (e.g. "Transact"). None
+    /// allows all of them.
+    pub requests: Option<HashSet<String>>,
+    /// Attributes and rules the client may read from. None allows
+    /// all of them.
+    pub read: Option<Vec<String>>,
+    /// Attributes and rules the client may write to, create, or
+    /// remove. None allows all of them.
+    pub write: Option<Vec<String>>,
+    /// Paths on the server's filesystem that the client may have
+    /// sources read from and sinks write to. Unlike the other
+    /// lists, None allows none of them.
+    pub files: Option<Vec<String>>,
+}
+
+/// A mapping from authentication tokens to the permissions they
+/// grant.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Policy {
+    /// Permissions by token.
+    pub tokens: HashMap<String, Permissions>,
+    /// Permissions of clients that have not authenticated. None
+    /// denies them everything but authentication.
+    pub anonymous: Option<Permissions>,
+}
+
+impl Policy {
+    /// Reads a policy from a json file.
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let mut contents = String::new();
+
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|error| Error::fault(format!("Failed to read policy: {}", error)))?;
+
+        serde_json::from_str(&contents).map_err(Error::incorrect)
+    }
+
+    /// Returns the permissions granted by the given token.
+    pub fn authenticate(&self, token: &str) -> Result<Permissions, Error> {
+        match self.tokens.get(token) {
+            None => Err(Error::forbidden("Unknown authentication token.")),
+            Some(permissions) => Ok(permissions.clone()),
+        }
+    }
+}
+
+impl Permissions {
+    /// Checks whether the request may be issued under these
+    /// permissions.
+    pub fn check(&self, req: &Request<Aid>) -> Result<(), Error> {
+        let kind = kind(req);
+
+        if let Some(ref requests) = self.requests {
+            if !requests.contains(kind) {
+                return Err(Error::forbidden(format!("{} requests are not allowed.", kind)));
+            }
+        }
+
+        if let Request::Setup(requests) = req {
+            for req in requests.iter() {
+                self.check(req)?;
+            }
+        }
+
+        let (reads, writes, files) = targets(req);
+
+        for name in reads.iter() {
+            if !self.may_read(name) {
+                return Err(Error::forbidden(format!("Reading {} is not allowed.", name)));
+            }
+        }
+
+        for name in writes.iter() {
+            if !allows(&self.write, name) {
+                return Err(Error::forbidden(format!("Writing {} is not allowed.", name)));
+            }
+        }
+
+        for path in files.iter() {
+            if self.files.is_none() || !allows(&self.files, path) {
+                return Err(Error::forbidden(format!("Accessing file {} is not allowed.", path)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the named attribute or rule may be read, e.g.
+    /// to filter what the client gets to see of the server's state.
+    pub fn may_read(&self, name: &str) -> bool {
+        allows(&self.read, name)
+    }
+}
+
+/// Checks a name against an optional list of allowed patterns.
+fn allows(patterns: &Option<Vec<String>>, name: &str) -> bool {
+    match patterns {
+        None => true,
+        Some(patterns) => patterns.iter().any(|pattern| {
+            if pattern.ends_with('*') {
+                name.starts_with(&pattern[..pattern.len() - 1])
+            } else {
+                pattern == name
+            }
+        }),
+    }
+}
+
+/// Returns the name of the request's type, as used in policies.
+fn kind(req: &Request<Aid>) -> &'static str {
+    match req {
+        Request::Transact(_) => "Transact",
+        Request::TransactReport(_) => "TransactReport",
+        Request::Subscribe(_) => "Subscribe",
+        #[cfg(feature = "graphql")]
+        Request::Derive(_, _) => "Derive",
+        Request::Interest(_) => "Interest",
+        Request::Query { .. } => "Query",
+        Request::Uninterest(_) => "Uninterest",
+        Request::Register(_) => "Register",
+        Request::RegisterSource(_) => "RegisterSource",
+        Request::RegisterConstraint(_) => "RegisterConstraint",
+        Request::Unregister(_) => "Unregister",
+        Request::CreateAttribute(_) => "CreateAttribute",
+        Request::DropAttribute(_) => "DropAttribute",
+        Request::AdvanceDomain(_, _) => "AdvanceDomain",
+        Request::Tick => "Tick",
+        Request::CloseInput(_) => "CloseInput",
+        Request::Disconnect => "Disconnect",
+        Request::Setup(_) => "Setup",
+        Request::Status => "Status",
+        Request::Schema => "Schema",
+        Request::Shutdown => "Shutdown",
+    }
+}
+
+/// Returns the names a request reads from and writes to, as well as
+/// the files it has the server access.
+fn targets(req: &Request<Aid>) -> (Vec<Aid>, Vec<Aid>, Vec<String>) {
+    match req {
+        Request::Transact(tx_data) | Request::TransactReport(tx_data) => {
+            let writes = tx_data
+                .iter()
+                .map(|Datom(_e, a, _v, _t, _diff)| a.clone())
+                .collect();
+
+            (Vec::new(), writes, Vec::new())
+        }
+        Request::Subscribe(name) => (vec![name.clone()], Vec::new(), Vec::new()),
+        Request::Interest(Interest { name, sink, .. }) => {
+            let mut writes = Vec::new();
+            let mut files = Vec::new();
+
+            match sink {
+                None => {}
+                Some(Sink::Attribute { name, .. }) => writes.push(name.clone()),
+                Some(Sink::TheVoid(path)) => files.extend(path.iter().cloned()),
+                #[cfg(feature = "csv-source")]
+                Some(Sink::CsvFile(sink)) => files.push(sink.path.clone()),
+                Some(Sink::JsonLines(sink)) => files.push(sink.path.clone()),
+                Some(Sink::AssocIn(_)) => {}
+            }
+
+            (vec![name.clone()], writes, files)
+        }
+        Request::Query { name, .. } => (vec![name.clone()], Vec::new(), Vec::new()),
+        // Derived attributes may expose any other attribute, so only
+        // clients that may read everything can derive them.
+        #[cfg(feature = "graphql")]
+        Request::Derive(namespace, _query) => (
+            vec!["*".to_string()],
+            vec![format!("{}/", namespace)],
+            Vec::new(),
+        ),
+        Request::Register(Register { rules, .. }) => {
+            let mut reads = Vec::new();
+            let mut writes = Vec::new();
+
+            for rule in rules.iter() {
+                let dependencies = rule.plan.dependencies();
+                reads.extend(dependencies.names.into_iter());
+                reads.extend(dependencies.attributes.into_iter());
+                writes.push(rule.name.clone());
+            }
+
+            (reads, writes, Vec::new())
+        }
+        Request::RegisterSource(source) => match source {
+            Source::TimelyLogging(source) => (Vec::new(), source.attributes.clone(), Vec::new()),
+            Source::DifferentialLogging(source) => {
+                (Vec::new(), source.attributes.clone(), Vec::new())
+            }
+            Source::DeclarativeLogging(source) => {
+                (Vec::new(), source.attributes.clone(), Vec::new())
+            }
+            #[cfg(feature = "csv-source")]
+            Source::CsvFile(source) => {
+                let writes = source.schema.iter().map(|(name, _)| name.clone()).collect();
+                (Vec::new(), writes, vec![source.path.clone()])
+            }
+        },
+        Request::CreateAttribute(CreateAttribute { name, .. }) => {
+            (Vec::new(), vec![name.clone()], Vec::new())
+        }
+        Request::RegisterConstraint(name)
+        | Request::Unregister(name)
+        | Request::DropAttribute(name)
+        | Request::CloseInput(name) => (Vec::new(), vec![name.clone()], Vec::new()),
+        _ => (Vec::new(), Vec::new(), Vec::new()),
+    }
+}
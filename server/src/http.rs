@@ -0,0 +1,501 @@
+//! A minimal HTTP/1.1 frontend for clients that can't speak
+//! websockets. Requests are mapped onto the same domain events as
+//! websocket messages, and answered from the same outputs.
+//!
+//! Supported endpoints:
+//!
+//! - `POST /requests` with a json array of requests in the body,
+//!   answered with `202 Accepted` once they have been handled, or
+//!   with the first error they caused
+//! - `GET /status`, answered with the server's status message
+//! - `GET /schema`, answered with all attributes and their configs
+//! - `GET /query/<name>`, answered with a one-shot snapshot
+//! - `GET /interest/<name>`, streaming results as server-sent events
+//!   until the client hangs up
+//!
+//! Names in paths are percent-decoded. Clients authenticate via an
+//! `Authorization: Bearer <token>` header.
+//!
+//! Requests whose line and headers exceed 16KiB are answered with
+//! `431`, bodies beyond 16MiB with `413`.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::SocketAddr;
+
+use slab::Slab;
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Poll, PollOpt, Ready, Token};
+
+use declarative_dataflow::server::{Interest, Request};
+use declarative_dataflow::{Error, Output};
+
+use crate::Aid;
+
+/// Token of the HTTP server socket.
+pub const HTTP_SERVER: Token = Token(std::usize::MAX - 4);
+
+/// HTTP connection tokens are offset by this much, s.t. they can't
+/// be confused with websocket connections.
+const HTTP_BASE: usize = std::usize::MAX / 2;
+
+/// Maximum number of concurrent HTTP connections.
+const MAX_CONNECTIONS: usize = 1024;
+
+/// Maximum size of a request's line and headers, in bytes.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Maximum size of a request's body, in bytes.
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Category of the message marking that a client's batch of
+/// requests has been handled. Sent to all HTTP clients, and to
+/// websocket clients that asked for it.
+pub const HANDLED: &str = "df/handled";
+
+/// Returns true iff the token identifies an HTTP connection.
+pub fn is_http(token: Token) -> bool {
+    token.0 >= HTTP_BASE && token.0 < HTTP_BASE + MAX_CONNECTIONS
+}
+
+/// An event on an HTTP connection that the rest of the server has
+/// to know about.
+pub enum HttpEvent {
+    /// A client issued requests, authenticating with the given
+    /// secret, if any.
+    Requests(Token, Option<String>, Vec<Request<Aid>>),
+    /// A client has went away.
+    Disconnect(Token),
+}
+
+/// How outputs addressed to a connection are to be answered.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Reply {
+    /// The first output completes the response.
+    Once,
+    /// Every output is sent as a server-sent event.
+    Stream,
+    /// The first error completes the response, otherwise it is
+    /// completed once the batch has been handled.
+    Batch,
+}
+
+struct HttpConnection {
+    socket: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    reply: Option<Reply>,
+    closing: bool,
+}
+
+/// State of all HTTP connections.
+pub struct Http {
+    listener: TcpListener,
+    connections: Slab<HttpConnection>,
+}
+
+impl Http {
+    /// Binds the HTTP server socket and registers it with the event
+    /// loop.
+    pub fn new(address: SocketAddr, poll: &Poll) -> Self {
+        let listener = TcpListener::bind(&address).expect("failed to create http socket");
+
+        poll.register(&listener, HTTP_SERVER, Ready::readable(), PollOpt::level())
+            .expect("failed to register http socket");
+
+        Http {
+            listener,
+            connections: Slab::with_capacity(MAX_CONNECTIONS),
+        }
+    }
+
+    /// Accepts all pending connections.
+    pub fn accept(&mut self, poll: &Poll) {
+        loop {
+            match self.listener.accept() {
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    error!("[HTTP] error while accepting connection {:?}", err);
+                    return;
+                }
+                Ok((socket, addr)) => {
+                    if self.connections.len() >= MAX_CONNECTIONS {
+                        warn!("[HTTP] rejecting connection from {}", addr);
+                        continue;
+                    }
+
+                    let entry = self.connections.vacant_entry();
+                    let token = Token(HTTP_BASE + entry.key());
+
+                    poll.register(&socket, token, Ready::readable(), PollOpt::level())
+                        .expect("failed to register http connection");
+
+                    entry.insert(HttpConnection {
+                        socket,
+                        incoming: Vec::new(),
+                        outgoing: Vec::new(),
+                        reply: None,
+                        closing: false,
+                    });
+
+                    info!("[HTTP] new connection from {} (token {:?})", addr, token);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of open connections.
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Returns the tokens of all connections streaming results.
+    pub fn streams(&self) -> Vec<Token> {
+        self.connections
+            .iter()
+            .filter(|(_key, conn)| conn.reply == Some(Reply::Stream))
+            .map(|(key, _conn)| Token(HTTP_BASE + key))
+            .collect()
+    }
+
+    /// Handles readiness of a connection.
+    pub fn ready(&mut self, poll: &Poll, token: Token, readiness: Ready) -> Vec<HttpEvent> {
+        let mut events = Vec::new();
+
+        let gone = match self.connections.get_mut(token.0 - HTTP_BASE) {
+            None => return events,
+            Some(conn) => {
+                let mut gone = false;
+
+                if readiness.is_readable() {
+                    gone = conn.read();
+
+                    if !gone && conn.reply.is_none() && !conn.closing {
+                        if let Some((secret, requests)) = conn.parse() {
+                            events.push(HttpEvent::Requests(token, secret, requests));
+                        }
+                    }
+                }
+
+                if !gone {
+                    gone = conn.flush();
+                }
+
+                gone || (conn.closing && conn.outgoing.is_empty())
+            }
+        };
+
+        if gone {
+            self.close(poll, token);
+            events.push(HttpEvent::Disconnect(token));
+        } else {
+            self.reregister(poll, token);
+        }
+
+        events
+    }
+
+    /// Starts the response to requests that have been authorized.
+    /// Nothing must be sent before, s.t. rejections can still be
+    /// answered properly.
+    pub fn authorized(&mut self, poll: &Poll, token: Token) {
+        if let Some(conn) = self.connections.get_mut(token.0 - HTTP_BASE) {
+            if conn.reply == Some(Reply::Stream) {
+                conn.outgoing.extend(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+                        .as_bytes(),
+                );
+            }
+        }
+
+        self.reregister(poll, token);
+    }
+
+    /// Answers a request that could not be accepted.
+    pub fn reject(&mut self, poll: &Poll, token: Token, error: &Error) {
+        if let Some(conn) = self.connections.get_mut(token.0 - HTTP_BASE) {
+            conn.respond(status(error), &serde_json::to_string(error).unwrap());
+        }
+
+        self.reregister(poll, token);
+    }
+
+    /// Forwards an output to the client.
+    pub fn send(&mut self, poll: &Poll, token: Token, out: &Output) {
+        let serialized = serde_json::to_string(out).expect("failed to serialize output");
+
+        match self.connections.get_mut(token.0 - HTTP_BASE) {
+            None => {
+                trace!("[HTTP] client {:?} has gone away", token);
+                return;
+            }
+            Some(conn) => match conn.reply {
+                None => {}
+                Some(_) if is_handled(out) && conn.reply != Some(Reply::Batch) => {}
+                Some(Reply::Batch) => match out {
+                    Output::Error(_, error, _) => conn.respond(status(error), &serialized),
+                    _ if is_handled(out) => conn.respond("202 Accepted", &serialized),
+                    _ => {}
+                },
+                Some(Reply::Once) => {
+                    let code = match out {
+                        Output::Error(_, error, _) => status(error),
+                        _ => "200 OK",
+                    };
+
+                    conn.respond(code, &serialized);
+                }
+                Some(Reply::Stream) => {
+                    conn.outgoing
+                        .extend(format!("data: {}\n\n", serialized).into_bytes());
+                }
+            },
+        }
+
+        self.reregister(poll, token);
+    }
+
+    fn reregister(&mut self, poll: &Poll, token: Token) {
+        if let Some(conn) = self.connections.get_mut(token.0 - HTTP_BASE) {
+            let gone = conn.flush();
+
+            if gone || (conn.closing && conn.outgoing.is_empty()) {
+                self.close(poll, token);
+            } else {
+                let interest = if conn.outgoing.is_empty() {
+                    Ready::readable()
+                } else {
+                    Ready::readable() | Ready::writable()
+                };
+
+                poll.reregister(&conn.socket, token, interest, PollOpt::level())
+                    .expect("failed to reregister http connection");
+            }
+        }
+    }
+
+    fn close(&mut self, poll: &Poll, token: Token) {
+        if self.connections.contains(token.0 - HTTP_BASE) {
+            let conn = self.connections.remove(token.0 - HTTP_BASE);
+            poll.deregister(&conn.socket).ok();
+        }
+    }
+}
+
+impl HttpConnection {
+    /// Reads everything available, but never more than a single
+    /// request may take up. Returns true iff the client has hung up.
+    fn read(&mut self) -> bool {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            if self.incoming.len() > MAX_HEAD_BYTES + MAX_BODY_BYTES {
+                return false;
+            }
+
+            match self.socket.read(&mut buffer) {
+                Ok(0) => return true,
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return false,
+                Err(err) => {
+                    trace!("[HTTP] error while reading: {}", err);
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Writes as much as possible. Returns true iff the client has
+    /// hung up.
+    fn flush(&mut self) -> bool {
+        while !self.outgoing.is_empty() {
+            match self.socket.write(&self.outgoing) {
+                Ok(0) => return true,
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return false,
+                Err(err) => {
+                    trace!("[HTTP] error while writing: {}", err);
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Queues a complete response, after which the connection is
+    /// closed.
+    fn respond(&mut self, code: &str, body: &str) {
+        self.outgoing.extend(
+            format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                code,
+                body.len(),
+                body
+            )
+            .into_bytes(),
+        );
+
+        self.reply = None;
+        self.closing = true;
+    }
+
+    /// Tries to parse a complete request from the incoming buffer
+    /// and maps it onto server requests, together with the secret
+    /// the client authenticates with. Malformed or oversized
+    /// requests are answered right away.
+    fn parse(&mut self) -> Option<(Option<String>, Vec<Request<Aid>>)> {
+        let head_end = match self.incoming.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(head_end) if head_end <= MAX_HEAD_BYTES => head_end,
+            None if self.incoming.len() <= MAX_HEAD_BYTES => return None,
+            _ => {
+                let error = Error::incorrect(format!("Request head exceeds {} bytes.", MAX_HEAD_BYTES));
+                self.respond(
+                    "431 Request Header Fields Too Large",
+                    &serde_json::to_string(&error).unwrap(),
+                );
+                return None;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&self.incoming[..head_end]).to_string();
+        let mut lines = head.split("\r\n");
+
+        let mut request_line = lines.next().unwrap_or("").split_whitespace();
+        let method = request_line.next().unwrap_or("").to_string();
+        let path = request_line.next().unwrap_or("").to_string();
+
+        let mut content_length = 0;
+        let mut authorization = None;
+
+        for line in lines {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap_or("").trim().to_lowercase();
+            let value = parts.next().unwrap_or("").trim();
+
+            if name == "content-length" {
+                content_length = value.parse().unwrap_or(0);
+            } else if name == "authorization" && value.starts_with("Bearer ") {
+                authorization = Some(value["Bearer ".len()..].to_string());
+            }
+        }
+
+        if content_length > MAX_BODY_BYTES {
+            let error = Error::incorrect(format!("Request body exceeds {} bytes.", MAX_BODY_BYTES));
+            self.respond("413 Payload Too Large", &serde_json::to_string(&error).unwrap());
+            return None;
+        }
+
+        let body_start = head_end + 4;
+        if self.incoming.len() < body_start + content_length {
+            return None;
+        }
+
+        let body = self.incoming[body_start..body_start + content_length].to_vec();
+        self.incoming.clear();
+
+        let mut segments = path.trim_start_matches('/').splitn(2, '/');
+        let endpoint = segments.next().unwrap_or("");
+        let name = match segments.next().map(percent_decode) {
+            None => None,
+            Some(Ok(name)) => Some(name),
+            Some(Err(error)) => {
+                self.respond("400 Bad Request", &serde_json::to_string(&error).unwrap());
+                return None;
+            }
+        };
+
+        let route = (method.as_str(), endpoint, name);
+
+        let requests = match route {
+            ("POST", "requests", None) => {
+                match serde_json::from_slice::<Vec<Request<Aid>>>(&body) {
+                    Err(error) => {
+                        self.respond("400 Bad Request", &serde_json::to_string(&Error::incorrect(error)).unwrap());
+                        return None;
+                    }
+                    Ok(requests) => {
+                        self.reply = Some(Reply::Batch);
+                        requests
+                    }
+                }
+            }
+            ("GET", "status", None) => {
+                self.reply = Some(Reply::Once);
+                vec![Request::Status]
+            }
+            ("GET", "schema", None) => {
+                self.reply = Some(Reply::Once);
+                vec![Request::Schema]
+            }
+            ("GET", "query", Some(name)) => {
+                self.reply = Some(Reply::Once);
+                vec![Request::Query { name, as_of: None }]
+            }
+            ("GET", "interest", Some(name)) => {
+                self.reply = Some(Reply::Stream);
+                vec![Request::Interest(Interest {
+                    name,
+                    granularity: None,
+                    sink: None,
+                    disable_logging: None,
+                    id: None,
+                    resume_from: None,
+                })]
+            }
+            _ => {
+                let error = Error::not_found(format!("No endpoint {} {}.", method, path));
+                self.respond("404 Not Found", &serde_json::to_string(&error).unwrap());
+                return None;
+            }
+        };
+
+        Some((authorization, requests))
+    }
+}
+
+/// Returns true iff the output marks a handled batch.
+fn is_handled(out: &Output) -> bool {
+    match out {
+        Output::Message(_, msg) => msg["category"] == HANDLED,
+        _ => false,
+    }
+}
+
+/// Decodes `%XX` escapes in a path segment.
+fn percent_decode(segment: &str) -> Result<String, Error> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let escape = segment
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| Error::incorrect(format!("Invalid escape in {}.", segment)))?;
+
+            decoded.push(escape);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(Error::incorrect)
+}
+
+/// Maps error categories onto HTTP status codes.
+fn status(error: &Error) -> &'static str {
+    match error.category.as_str() {
+        "df.error.category/incorrect" => "400 Bad Request",
+        "df.error.category/forbidden" => "403 Forbidden",
+        "df.error.category/not-found" => "404 Not Found",
+        "df.error.category/conflict" => "409 Conflict",
+        "df.error.category/unsupported" => "501 Not Implemented",
+        _ => "500 Internal Server Error",
+    }
+}
@@ -6,15 +6,16 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 use std::time::{Duration, Instant};
 
 use timely::dataflow::channels::pact::{Exchange, Pipeline};
 use timely::dataflow::operators::generic::OutputHandle;
-use timely::dataflow::operators::{Operator, Probe};
+use timely::dataflow::operators::{Operator, Probe, ToStream};
 use timely::logging::{Logger, TimelyEvent};
+use timely::order::PartialOrder;
 use timely::synchronization::Sequencer;
 
 use differential_dataflow::logging::DifferentialEvent;
@@ -23,10 +24,12 @@ use differential_dataflow::operators::Consolidate;
 use declarative_dataflow::scheduling::{AsScheduler, SchedulingEvent};
 use declarative_dataflow::server;
 use declarative_dataflow::server::{CreateAttribute, Request, Server, TxId};
-use declarative_dataflow::sinks::{Sinkable, SinkingContext};
+use declarative_dataflow::sinks::{drain_complete, Sink, Sinkable, SinkingContext};
 use declarative_dataflow::timestamp::{Coarsen, Time};
-use declarative_dataflow::{Output, ResultDiff};
+use declarative_dataflow::{Error, Output, ResultDiff, Value};
 
+mod auth;
+mod http;
 mod networking;
 use crate::networking::{DomainEvent, Token, IO, SYSTEM};
 
@@ -51,8 +54,16 @@ type T = Pair<Duration, u64>;
 struct Configuration {
     /// Port at which client connections should be accepted.
     pub port: u16,
+    /// Port at which plain HTTP connections should be accepted.
+    pub http_port: Option<u16>,
     /// File from which to read server configuration.
     pub config: Option<String>,
+    /// File from which to read the access control policy.
+    pub policy: Option<String>,
+    /// File from which to read requests to apply at startup.
+    pub setup: Option<String>,
+    /// How long to wait for dataflows to complete when shutting down.
+    pub shutdown_timeout: Duration,
     /// Number of threads to use.
     pub threads: usize,
     /// Number of processes to expect over the entire cluster.
@@ -69,7 +80,11 @@ impl Default for Configuration {
     fn default() -> Self {
         Configuration {
             port: 6262,
+            http_port: None,
             config: None,
+            policy: None,
+            setup: None,
+            shutdown_timeout: Duration::from_secs(30),
             threads: 1,
             processes: 1,
             addresses: vec!["localhost:2101".to_string()],
@@ -86,7 +101,11 @@ impl Configuration {
         let mut opts = getopts::Options::new();
 
         opts.optopt("", "port", "server port", "PORT");
+        opts.optopt("", "http-port", "http server port", "PORT");
         opts.optopt("", "config", "server configuration file", "FILE");
+        opts.optopt("", "policy", "access control policy file", "FILE");
+        opts.optopt("", "setup", "file of requests to apply at startup", "FILE");
+        opts.optopt("", "shutdown-timeout", "how long to wait for dataflows on shutdown", "SECONDS");
 
         // Timely arguments.
         opts.optopt(
@@ -120,6 +139,10 @@ impl Configuration {
             .map(|x| x.parse().expect("failed to parse port"))
             .unwrap_or(default.port);
 
+        let http_port = matches
+            .opt_str("http-port")
+            .map(|x| x.parse().expect("failed to parse http port"));
+
         let threads = matches
             .opt_str("w")
             .map(|x| x.parse().expect("failed to parse threads"))
@@ -162,7 +185,14 @@ impl Configuration {
 
         Self {
             port,
+            http_port,
             config: matches.opt_str("config"),
+            policy: matches.opt_str("policy"),
+            setup: matches.opt_str("setup"),
+            shutdown_timeout: matches
+                .opt_str("shutdown-timeout")
+                .map(|x| Duration::from_secs(x.parse().expect("failed to parse shutdown timeout")))
+                .unwrap_or(default.shutdown_timeout),
             threads,
             processes,
             addresses,
@@ -191,6 +221,28 @@ impl Into<server::Configuration> for Configuration {
     }
 }
 
+impl Configuration {
+    /// Reads the requests to apply at startup, after the server's
+    /// built-in ones.
+    pub fn setup(&self) -> Result<Vec<Request<Aid>>, Error> {
+        let mut requests = Server::<Aid, T, Token>::builtins();
+
+        if let Some(ref path) = self.setup {
+            let mut contents = String::new();
+
+            File::open(path)
+                .and_then(|mut file| file.read_to_string(&mut contents))
+                .map_err(|error| Error::fault(format!("Failed to read setup: {}", error)))?;
+
+            let setup: Vec<Request<Aid>> = serde_json::from_str(&contents).map_err(Error::incorrect)?;
+
+            requests.extend(setup.into_iter());
+        }
+
+        Ok(requests)
+    }
+}
+
 impl Into<timely::Configuration> for Configuration {
     fn into(self) -> timely::Configuration {
         if self.processes > 1 {
@@ -220,6 +272,86 @@ struct Command {
     pub client: usize,
     /// Requests issued by the client.
     pub requests: Vec<Request<Aid>>,
+    /// Why the client's requests were refused before they could be
+    /// sequenced, if they were. Refused commands carry no requests,
+    /// but are sequenced nonetheless, s.t. the client learns about
+    /// the refusal under the command's tx.
+    pub refusal: Option<Error>,
+}
+
+/// Keeps I/O going while shutting down, answering new commands with
+/// an error and discarding sequenced ones.
+fn refuse_commands(
+    io: &mut IO,
+    sequencer: &mut Sequencer<Command>,
+    next_tx: TxId,
+    interests: &HashMap<String, HashSet<Token>>,
+) {
+    io.step(interests);
+
+    while let Some(event) = io.next() {
+        match event {
+            DomainEvent::Requests(token, _) | DomainEvent::Refused(token, _) => {
+                let error = Error::conflict("The server is shutting down.");
+                io.send.send(Output::Error(token.into(), error, next_tx)).unwrap();
+            }
+            DomainEvent::Disconnect(_token) => {}
+        }
+    }
+
+    while let Some(command) = sequencer.next() {
+        trace!("dropping {} requests by client {}", command.requests.len(), command.client);
+    }
+}
+
+/// Removes everything from a worker's status report that the
+/// permissions don't allow the client to read.
+fn filter_status(status: &mut serde_json::Value, permissions: &auth::Permissions) {
+    for field in &["attributes", "interests"] {
+        if let Some(entries) = status[*field].as_object() {
+            let readable: serde_json::Map<String, serde_json::Value> = entries
+                .iter()
+                .filter(|(name, _)| permissions.may_read(name))
+                .map(|(name, entry)| (name.clone(), entry.clone()))
+                .collect();
+
+            status[*field] = serde_json::Value::Object(readable);
+        }
+    }
+
+    for field in &["rules", "constraints"] {
+        if let Some(names) = status[*field].as_array() {
+            let readable: Vec<serde_json::Value> = names
+                .iter()
+                .filter(|name| name.as_str().map_or(false, |name| permissions.may_read(name)))
+                .cloned()
+                .collect();
+
+            status[*field] = serde_json::Value::Array(readable);
+        }
+    }
+}
+
+/// Notifies interested clients of the named query, whenever the
+/// frontier has moved on since the last notification.
+fn notify_progress(
+    send_results: &mio_extras::channel::Sender<(Token, Output)>,
+    client: Token,
+    name: &str,
+    frontier: &[T],
+    last_frontier: &mut Option<Vec<T>>,
+) {
+    if last_frontier.as_ref().map_or(false, |last| &last[..] == frontier) {
+        return;
+    }
+
+    *last_frontier = Some(frontier.to_vec());
+
+    let frontier = frontier.iter().cloned().map(|t| t.into()).collect();
+
+    send_results
+        .send((client, Output::Progress(name.to_string(), frontier)))
+        .expect("internal channel send failed");
 }
 
 fn main() {
@@ -229,6 +361,10 @@ fn main() {
     let timely_config: timely::Configuration = config.clone().into();
     let server_config: server::Configuration = config.clone().into();
 
+    // All workers must apply the exact same setup, so we read it
+    // only once per process.
+    let setup = config.setup().expect("failed to load setup");
+
     timely::execute(timely_config, move |worker| {
         // Initialize server state (no networking).
         let mut server = Server::<Aid, T, Token>::new_at(server_config.clone(), worker.timer());
@@ -239,20 +375,28 @@ fn main() {
         }
 
         // The server might specify a sequence of requests for
-        // setting-up built-in arrangements. We serialize those here
-        // and pre-load the sequencer with them, such that they will
-        // flow through the regular request handling.
-        let builtins = Server::<Aid, T, Token>::builtins();
+        // setting-up built-in arrangements, to which operators can
+        // add their own. We pre-load each worker's sequencer with
+        // them, such that they will flow through the regular request
+        // handling, ahead of any client requests. As every worker
+        // handles its own copy, they must agree on a single owner,
+        // lest transactions be applied once per worker.
         let preload_command = Command {
-            owner: worker.index(),
+            owner: 0,
             client: SYSTEM.0,
-            requests: builtins,
+            requests: vec![Request::Setup(setup.clone())],
+            refusal: None,
         };
 
         // Setup serializing command stream between all workers.
         let mut sequencer: Sequencer<Command> =
             Sequencer::preloaded(worker, Instant::now(), VecDeque::from(vec![preload_command]));
 
+        // Number of commands this worker has pushed to the sequencer,
+        // that haven't come out of it yet. Worker 0 owns the preloaded
+        // command, which therefore counts as pushed there.
+        let mut unsequenced: usize = if worker.index() == 0 { 1 } else { 0 };
+
         // Kickoff ticking, if configured. We only want to issue ticks
         // from a single worker, to avoid redundant ticking.
         if worker.index() == 0 && server_config.tick.is_some() {
@@ -260,7 +404,9 @@ fn main() {
                 owner: 0,
                 client: SYSTEM.0,
                 requests: vec![Request::Tick],
+                refusal: None,
             });
+            unsequenced += 1;
         }
 
         // Set up I/O event loop.
@@ -270,7 +416,16 @@ fn main() {
             // let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), config.port);
             let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0,0,0,0)), config.port);
 
-            IO::new(addr)
+            let policy = config
+                .policy
+                .as_ref()
+                .map(|path| auth::Policy::from_file(path).expect("failed to load policy"));
+
+            let http_addr = config
+                .http_port
+                .map(|port| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0,0,0,0)), port));
+
+            IO::new(addr, http_addr, policy)
         };
 
         info!(
@@ -318,7 +473,9 @@ fn main() {
                                     owner: worker.index(),
                                     client: SYSTEM.0,
                                     requests: vec![Request::Tick],
+                                    refusal: None,
                                 });
+                                unsequenced += 1;
                             }
                         }
                     }
@@ -330,7 +487,7 @@ fn main() {
             }
 
             // Transform low-level I/O events into domain events.
-            io.step(next_tx, &server.interests);
+            io.step(&server.interests);
 
             while let Some(event) = io.next() {
                 match event {
@@ -340,7 +497,19 @@ fn main() {
                             owner: worker.index(),
                             client: token.into(),
                             requests,
+                            refusal: None,
+                        });
+                        unsequenced += 1;
+                    }
+                    DomainEvent::Refused(token, error) => {
+                        trace!("[IO] refused command");
+                        sequencer.push(Command {
+                            owner: worker.index(),
+                            client: token.into(),
+                            requests: Vec::new(),
+                            refusal: Some(error),
                         });
+                        unsequenced += 1;
                     }
                     DomainEvent::Disconnect(token) => {
                         info!("[IO] token={:?} disconnected", token);
@@ -348,14 +517,20 @@ fn main() {
                             owner: worker.index(),
                             client: token.into(),
                             requests: vec![Request::Disconnect],
+                            refusal: None,
                         });
+                        unsequenced += 1;
                     }
                 }
             }
 
             // handle commands
 
-            while let Some(mut command) = sequencer.next() {
+            let mut commands: VecDeque<Command> = std::iter::from_fn(|| sequencer.next()).collect();
+
+            unsequenced -= commands.iter().filter(|command| command.owner == worker.index()).count();
+
+            while let Some(mut command) = commands.pop_front() {
 
                 // Count-up sequence numbers.
                 next_tx += 1;
@@ -366,14 +541,60 @@ fn main() {
                 let client = command.client;
                 let last_tx = next_tx - 1;
 
-                for req in command.requests.drain(..) {
+                if let Some(error) = command.refusal.take() {
+                    if worker.index() == owner {
+                        io.send.send(Output::Error(client, error, last_tx)).unwrap();
+                    }
+                }
+
+                // Setup requests are unpacked in place. Failures
+                // during startup leave the server in a state nobody
+                // asked for, so they are fatal.
+                let mut is_setup = false;
+                let is_disconnect = command.requests.iter().any(|req| match req {
+                    Request::Disconnect => true,
+                    _ => false,
+                });
+                let requests: Vec<Request<Aid>> = command
+                    .requests
+                    .drain(..)
+                    .flat_map(|req| match req {
+                        Request::Setup(setup) => {
+                            is_setup = true;
+                            setup
+                        }
+                        req => vec![req],
+                    })
+                    .collect();
+
+                for (index, req) in requests.into_iter().enumerate() {
 
                     // @TODO only create a single dataflow, but only if req != Transact
 
                     trace!("[W{}] {:?}", worker.index(), req);
 
                     let result = match req {
-                        Request::Transact(req) => server.transact(req, owner, worker.index()),
+                        Request::Transact(req) => {
+                            server
+                                .watch_constraints(&req, owner, worker.index(), Token(client), last_tx)
+                                .and_then(|_| server.transact(req, owner, worker.index()))
+                        }
+                        Request::TransactReport(req) => {
+                            let reported = server
+                                .watch_constraints(&req, owner, worker.index(), Token(client), last_tx)
+                                .and_then(|_| server.transact_reported(req, owner, worker.index(), Token(client), last_tx));
+
+                            match reported {
+                                Err(error) => Err(error),
+                                Ok(time) => {
+                                    if owner == worker.index() {
+                                        io.send.send(Output::TxReport(client, last_tx, time.into())).unwrap();
+                                    }
+
+                                    Ok(())
+                                }
+                            }
+                        }
                         Request::Subscribe(aid) => {
                             let interests = server.interests
                                 .entry(aid.clone())
@@ -391,19 +612,14 @@ fn main() {
                                 let send_results = io.send.clone();
 
                                 let result = worker.dataflow::<T, _, _>(|scope| {
-                                    let (propose, shutdown) = server
-                                        .internal
-                                        .forward_propose(&aid)
-                                        .unwrap()
-                                        .import_frontier(scope, &aid);
-
-                                    // @TODO stash this somewhere
-                                    std::mem::forget(shutdown);
+                                    let tuples = match server.subscribe(aid.clone(), scope) {
+                                        Err(error) => { return Err(error); }
+                                        Ok(tuples) => tuples,
+                                    };
 
                                     let pact = Exchange::new(move |_| owner as u64);
 
-                                    propose
-                                        .as_collection(|e, v| vec![e.clone(), v.clone()])
+                                    tuples
                                         .inner
                                         .unary(pact, "Subscription", move |_cap, _info| {
                                             move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
@@ -445,21 +661,29 @@ fn main() {
 
                             Ok(())
                         }
+                        Request::Interest(ref req) if req.resume_from.is_some() && req.id.is_none() => {
+                            Err(Error::incorrect("Resuming a subscription requires an id."))
+                        }
                         Request::Interest(req) => {
+                            let key = req.key().to_string();
                             let interests = server.interests
-                                .entry(req.name.clone())
+                                .entry(key.clone())
                                 .or_insert_with(HashSet::new);
 
-                            // We need to check this, because we only want to setup
-                            // the dataflow on the first interest.
+                            // We need to check this, because we only want to arrange
+                            // the results on the first interest.
                             let was_first = interests.is_empty();
 
-                            // All workers keep track of every client's interests, s.t. they
-                            // know when to clean up unused dataflows.
-                            interests.insert(Token(client));
+                            if req.id.is_some() && !was_first {
+                                Err(Error::conflict(format!("Subscription {} exists already.", key)))
+                            } else {
+                                // All workers keep track of every client's interests, s.t. they
+                                // know when to clean up unused dataflows.
+                                interests.insert(Token(client));
 
-                            if was_first {
-                                let send_results = io.send.clone();
+                                let send_results = io.send_direct.clone();
+                                let is_owner = worker.index() == owner;
+                                let progress_name = key.clone();
 
                                 let disable_logging = req.disable_logging.unwrap_or(false);
                                 let mut timely_logger = None;
@@ -471,80 +695,133 @@ fn main() {
                                     differential_logger = worker.log_register().remove("differential/arrange");
                                 }
 
-                                let result = worker.dataflow::<T, _, _>(|scope| {
-                                    let sink_context: SinkingContext = (&req).into();
+                                // Plain interests share the arranged results, whereas
+                                // subscriptions with an id get a dataflow of their own.
+                                // Either way, each client gets its own view, delayed and
+                                // sunk as requested.
+                                let mut result = Ok(());
 
-                                    let relation = match server.interest(req.name, scope) {
-                                        Err(error) => { return Err(error); }
-                                        Ok(relation) => relation,
-                                    };
+                                if was_first && req.id.is_none() {
+                                    result = worker.dataflow::<T, _, _>(|scope| server.share(req.name.clone(), scope));
+                                }
 
-                                    let delayed = match req.granularity {
-                                        None => relation.consolidate(),
-                                        Some(granularity) => {
-                                            let granularity: T = granularity.into();
-                                            relation
-                                                .delay(move |t| t.coarsen(&granularity))
-                                                .consolidate()
-                                        }
-                                    };
+                                if result.is_ok() {
+                                    result = worker.dataflow::<T, _, _>(|scope| {
+                                        let sink_context: SinkingContext = (&req).into();
 
-                                    let pact = Exchange::new(move |_| owner as u64);
+                                        let relation = match req.id {
+                                            None => server.view(req.name, Token(client), scope),
+                                            Some(id) => {
+                                                let resume_from = req.resume_from.map(|since| since.into());
+                                                server.subscription(id, req.name, resume_from, scope)
+                                            }
+                                        };
+
+                                        let relation = match relation {
+                                            Err(error) => { return Err(error); }
+                                            Ok(relation) => relation,
+                                        };
+
+                                        let delayed = match req.granularity {
+                                            None => relation.consolidate(),
+                                            Some(granularity) => {
+                                                let granularity: T = granularity.into();
+                                                relation
+                                                    .delay(move |t| t.coarsen(&granularity))
+                                                    .consolidate()
+                                            }
+                                        };
 
-                                    match req.sink {
-                                        Some(sink) => {
-                                            let sunk = match sink.sink(&delayed.inner, pact, &mut server.probe, sink_context) {
-                                                Err(error) => { return Err(error); }
-                                                Ok(sunk) => sunk,
-                                            };
+                                        let pact = Exchange::new(move |_| owner as u64);
 
-                                            if let Some(sunk) = sunk {
+                                        match req.sink {
+                                            Some(Sink::Attribute { name, e_offset, v_offset }) => {
+                                                server.sink_attribute(scope, &delayed.inner, pact, name, e_offset, v_offset)
+                                            }
+                                            Some(sink) => {
+                                                let sunk = match sink.sink(&delayed.inner, pact, &mut server.probe, sink_context) {
+                                                    Err(error) => { return Err(error); }
+                                                    Ok(sunk) => sunk,
+                                                };
+
+                                                if let Some(sunk) = sunk {
+                                                    let mut vector = Vec::new();
+                                                    let mut last_frontier: Option<Vec<T>> = None;
+
+                                                    sunk
+                                                        .unary_frontier(Pipeline, "SinkResults", move |_cap, _info| {
+                                                            move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                                                                input.for_each(|_time, data| {
+                                                                    data.swap(&mut vector);
+
+                                                                    for out in vector.drain(..) {
+                                                                        send_results.send((Token(client), out))
+                                                                            .expect("internal channel send failed");
+                                                                    }
+                                                                });
+
+                                                                if is_owner {
+                                                                    notify_progress(&send_results, Token(client), &progress_name, input.frontier().frontier(), &mut last_frontier);
+                                                                }
+                                                            }
+                                                        })
+                                                        .probe_with(&mut server.probe);
+                                                }
+
+                                                Ok(())
+                                            }
+                                            None => {
+                                                let mut recvd: Vec<ResultDiff<T>> = Vec::new();
                                                 let mut vector = Vec::new();
-                                                sunk
-                                                    .unary(Pipeline, "SinkResults", move |_cap, _info| {
+                                                let mut last_frontier: Option<Vec<T>> = None;
+
+                                                delayed
+                                                    .inner
+                                                    .unary_frontier(pact, "ResultsRecv", move |_cap, _info| {
                                                         move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                                                            // due to the exchange pact, this closure is only
+                                                            // executed by the owning worker
+
                                                             input.for_each(|_time, data| {
                                                                 data.swap(&mut vector);
+                                                                recvd.extend(vector.drain(..));
+                                                            });
 
-                                                                for out in vector.drain(..) {
-                                                                    send_results.send(out)
-                                                                        .expect("internal channel send failed");
+                                                            // Results are held back until their
+                                                            // timestamp has been closed, and then
+                                                            // forwarded one consolidated batch per
+                                                            // timestamp, each followed by a marker.
+                                                            let mut complete = drain_complete(&mut recvd, input.frontier()).into_iter().peekable();
+
+                                                            while let Some((tuple, time, diff)) = complete.next() {
+                                                                let mut batch = vec![(tuple, time.clone().into(), diff)];
+
+                                                                while complete.peek().map_or(false, |next| next.1 == time) {
+                                                                    let (tuple, t, diff) = complete.next().unwrap();
+                                                                    batch.push((tuple, t.into(), diff));
                                                                 }
-                                                            });
+
+                                                                send_results
+                                                                    .send((Token(client), Output::QueryDiff(sink_context.name.clone(), batch)))
+                                                                    .expect("internal channel send failed");
+
+                                                                send_results
+                                                                    .send((Token(client), Output::Complete(sink_context.name.clone(), time.into())))
+                                                                    .expect("internal channel send failed");
+                                                            }
+
+                                                            if is_owner {
+                                                                notify_progress(&send_results, Token(client), &progress_name, input.frontier().frontier(), &mut last_frontier);
+                                                            }
                                                         }
                                                     })
                                                     .probe_with(&mut server.probe);
-                                            }
-
-                                            Ok(())
-                                        }
-                                        None => {
-                                            delayed
-                                                .inner
-                                                .unary(pact, "ResultsRecv", move |_cap, _info| {
-                                                    move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
-                                                        // due to the exchange pact, this closure is only
-                                                        // executed by the owning worker
-
-                                                        // @TODO only forward inputs up to the frontier!
-
-                                                        input.for_each(|_time, data| {
-                                                            let data = data.iter()
-                                                                .map(|(tuple, t, diff)| (tuple.clone(), t.clone().into(), *diff))
-                                                                .collect::<Vec<ResultDiff<Time>>>();
-
-                                                            send_results
-                                                                .send(Output::QueryDiff(sink_context.name.clone(), data))
-                                                                .expect("internal channel send failed");
-                                                        });
-                                                    }
-                                                })
-                                                .probe_with(&mut server.probe);
 
-                                            Ok(())
+                                                Ok(())
+                                            }
                                         }
-                                    }
-                                });
+                                    });
+                                }
 
                                 if disable_logging {
                                     if let Some(logger) = timely_logger {
@@ -564,13 +841,107 @@ fn main() {
                                     }
                                 }
 
+                                // Nothing is being served, so the next
+                                // interest has to try again.
+                                if result.is_err() {
+                                    server.uninterest(Token(client), &key).ok();
+                                }
+
                                 result
-                            } else {
-                                Ok(())
                             }
                         }
+                        Request::Query { name, as_of } => {
+                            let send_results = io.send.clone();
+                            let is_owner = worker.index() == owner;
+
+                            let as_of: T = match as_of {
+                                None => server.internal.epoch().clone(),
+                                Some(as_of) => as_of.into(),
+                            };
+
+                            worker.dataflow::<T, _, _>(|scope| {
+                                let (relation, shutdown_handle) = match server.query(name.clone(), scope) {
+                                    Err(error) => { return Err(error); }
+                                    Ok(implemented) => implemented,
+                                };
+
+                                let pact = Exchange::new(move |_| owner as u64);
+
+                                let mut shutdown_handle = Some(shutdown_handle);
+                                let mut snapshot: BTreeMap<Vec<Value>, isize> = BTreeMap::new();
+                                let mut vector = Vec::new();
+
+                                relation
+                                    .inner
+                                    .unary_frontier(pact, "Snapshot", move |_cap, _info| {
+                                        move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                                            input.for_each(|_time, data| {
+                                                data.swap(&mut vector);
+
+                                                for (tuple, t, diff) in vector.drain(..) {
+                                                    if t.less_than(&as_of) {
+                                                        *snapshot.entry(tuple).or_insert(0) += diff;
+                                                    }
+                                                }
+                                            });
+
+                                            if shutdown_handle.is_none() {
+                                                return;
+                                            }
+
+                                            // Nothing before as_of can arrive anymore, once
+                                            // the whole frontier has caught up to it.
+                                            let is_complete = input
+                                                .frontier()
+                                                .frontier()
+                                                .iter()
+                                                .all(|t| as_of.less_equal(t));
+
+                                            if is_complete {
+                                                if is_owner {
+                                                    let time: Time = as_of.clone().into();
+                                                    let results = std::mem::replace(&mut snapshot, BTreeMap::new())
+                                                        .into_iter()
+                                                        .filter(|(_tuple, count)| *count != 0)
+                                                        .map(|(tuple, count)| (tuple, time.clone(), count))
+                                                        .collect();
+
+                                                    send_results
+                                                        .send(Output::Snapshot(client, name.clone(), time, results))
+                                                        .expect("internal channel send failed");
+                                                }
+
+                                                // Dropping the handle shuts down the dataflow.
+                                                shutdown_handle = None;
+                                            }
+                                        }
+                                    })
+                                    .probe_with(&mut server.probe);
+
+                                Ok(())
+                            })
+                        }
                         Request::Uninterest(name) => server.uninterest(Token(command.client), &name),
-                        Request::Register(req) => server.register(req),
+                        Request::Register(req) => match server.register(req) {
+                            Err(error) => Err(error),
+                            Ok(evicted) => {
+                                // Clients have to express their interest
+                                // anew, to receive results under the new
+                                // definitions.
+                                for (name, clients) in evicted.into_iter() {
+                                    for Token(interested) in clients.into_iter() {
+                                        let error = Error::conflict(format!(
+                                            "{} depends on a redefined rule and has been shut down.",
+                                            name
+                                        ));
+
+                                        io.send.send(Output::Error(interested, error, last_tx)).unwrap();
+                                    }
+                                }
+
+                                Ok(())
+                            }
+                        },
                         Request::RegisterSource(source) => {
                             worker.dataflow::<T, _, _>(|scope| {
                                 server.register_source(Box::new(source), scope)
@@ -581,10 +952,17 @@ fn main() {
                                 server.create_attribute(scope, name, config)
                             })
                         }
+                        Request::RegisterConstraint(name) => {
+                            worker.dataflow::<T, _, _>(|scope| {
+                                server.register_constraint(name, scope)
+                            })
+                        }
+                        Request::Unregister(name) => server.unregister(name),
+                        Request::DropAttribute(name) => server.drop_attribute(name),
                         Request::AdvanceDomain(name, next) => server.advance_domain(name, next.into()),
                         Request::CloseInput(name) => server.internal.close_input(name),
                         Request::Disconnect => server.disconnect_client(Token(command.client)),
-                        Request::Setup => unimplemented!(),
+                        Request::Setup(_) => Err(Error::incorrect("Setup requests can't be nested.")),
                         Request::Tick => {
                             // We don't actually have to do any actual worker here, because we are
                             // ticking the domain on each command anyways. We do have to schedule
@@ -603,12 +981,85 @@ fn main() {
                             Ok(())
                         }
                         Request::Status => {
-                            let status = serde_json::json!({
-                                "category": "df/status",
-                                "message": "running",
+                            let send_results = io.send.clone();
+                            let is_owner = worker.index() == owner;
+                            let permissions = if is_owner { io.permissions(Token(client)) } else { None };
+
+                            let mut status = server.status();
+                            status["worker"] = serde_json::json!(worker.index());
+                            status["connections"] = serde_json::json!(io.connection_count());
+                            // The sequencer doesn't expose its queue, but we
+                            // know how many of our own commands are still
+                            // in it, and how many sequenced commands are
+                            // waiting behind this one.
+                            status["unsequenced_commands"] = serde_json::json!(unsequenced);
+                            status["queued_commands"] = serde_json::json!(commands.len());
+
+                            // Every worker reports its own view, which
+                            // the owner collects into a single message.
+                            worker.dataflow::<T, _, _>(|scope| {
+                                let pact = Exchange::new(move |_| owner as u64);
+
+                                let mut statuses = Vec::new();
+                                let mut vector = Vec::new();
+
+                                vec![status.to_string()]
+                                    .to_stream(scope)
+                                    .unary_frontier(pact, "Status", move |_cap, _info| {
+                                        move |input, _output: &mut OutputHandle<_, (), _>| {
+                                            input.for_each(|_time, data| {
+                                                data.swap(&mut vector);
+                                                statuses.extend(vector.drain(..));
+                                            });
+
+                                            if is_owner && !statuses.is_empty() && input.frontier().frontier().is_empty() {
+                                                let mut workers: Vec<serde_json::Value> = statuses
+                                                    .drain(..)
+                                                    .map(|status: String| serde_json::from_str(&status).unwrap())
+                                                    .collect();
+
+                                                if let Some(ref permissions) = permissions {
+                                                    for status in workers.iter_mut() {
+                                                        filter_status(status, permissions);
+                                                    }
+                                                }
+
+                                                workers.sort_by_key(|status| status["worker"].as_u64());
+
+                                                let status = serde_json::json!({
+                                                    "category": "df/status",
+                                                    "message": "running",
+                                                    "workers": workers,
+                                                });
+
+                                                send_results.send(Output::Message(client, status)).unwrap();
+                                            }
+                                        }
+                                    });
                             });
 
-                            io.send.send(Output::Message(client, status)).unwrap();
+                            Ok(())
+                        }
+                        Request::Schema => {
+                            // All workers know about all attributes.
+                            if worker.index() == owner {
+                                let permissions = io.permissions(Token(client));
+                                let attributes: Vec<CreateAttribute> = server
+                                    .schema()
+                                    .into_iter()
+                                    .filter(|(name, _config)| {
+                                        permissions.as_ref().map_or(true, |permissions| permissions.may_read(name))
+                                    })
+                                    .map(|(name, config)| CreateAttribute { name, config })
+                                    .collect();
+
+                                let schema = serde_json::json!({
+                                    "category": "df/schema",
+                                    "attributes": attributes,
+                                });
+
+                                io.send.send(Output::Message(client, schema)).unwrap();
+                            }
 
                             Ok(())
                         }
@@ -619,10 +1070,44 @@ fn main() {
                     };
 
                     if let Err(error) = result {
-                        io.send.send(Output::Error(client, error, last_tx)).unwrap();
+                        if is_setup && client == SYSTEM.0 {
+                            error!("[W{}] setup request {} failed: {:?}", worker.index(), index, error);
+                            panic!("setup failed at request {}: {}", index, error.message);
+                        }
+
+                        // Only the owner is connected to the client,
+                        // other workers may use its token for others.
+                        if worker.index() == owner {
+                            io.send.send(Output::Error(client, error, last_tx)).unwrap();
+                        }
+                    }
+
+                    // Everything sequenced after a shutdown is
+                    // dropped, identically on all workers.
+                    if shutdown {
+                        break;
                     }
                 }
 
+                // Clients can't tell otherwise when their batch has
+                // been handled without errors, unless it happens to
+                // be reported. Websocket clients have to ask for
+                // this, as older ones don't expect it.
+                if worker.index() == owner
+                    && client != SYSTEM.0
+                    && !is_disconnect
+                    && io.acknowledges(Token(client))
+                {
+                    let time: Time = server.internal.epoch().clone().into();
+                    let handled = serde_json::json!({
+                        "category": http::HANDLED,
+                        "tx": last_tx,
+                        "time": time,
+                    });
+
+                    io.send.send(Output::Message(client, handled)).unwrap();
+                }
+
                 if !server_config.manual_advance {
                     #[cfg(all(not(feature = "real-time"), not(feature = "bitemporal")))]
                     let next = next_tx as u64;
@@ -633,6 +1118,10 @@ fn main() {
 
                     server.internal.advance_epoch(next).expect("failed to advance epoch");
                 }
+
+                if shutdown {
+                    break;
+                }
             }
 
             // We must always ensure that workers step in every
@@ -644,11 +1133,34 @@ fn main() {
                 worker.step();
             }
 
+            // Transactions violating constraints are rolled back
+            // before anyone is told about their visibility.
+            match server.check_constraints() {
+                Err(error) => error!("[W{}] failed to check constraints: {:?}", worker.index(), error),
+                Ok(rejected) => {
+                    for (Token(client), tx, error) in rejected.into_iter() {
+                        io.send.send(Output::Error(client, error, tx)).unwrap();
+                    }
+                }
+            }
+
+            for (Token(client), tx) in server.visible_transactions() {
+                io.send.send(Output::TxVisible(client, tx)).unwrap();
+            }
+
+            // Results that attribute sinks have produced in the
+            // meantime are fed back into the domain, just like a
+            // regular transaction.
+            if let Err(error) = server.transact_sunk() {
+                error!("[W{}] failed to transact sunk results: {:?}", worker.index(), error);
+            }
+
             // We advance before `step_or_park`, because advancing
             // might take a decent amount of time, in case traces get
             // compacted. If that happens, we can park less before
             // scheduling the next activator.
             server.internal.advance().expect("failed to advance domain");
+            server.advance_results();
 
             // Finally, we give the CPU a chance to chill, if no work
             // remains.
@@ -658,11 +1170,51 @@ fn main() {
 
         info!("[W{}] shutting down", worker.index());
 
-        drop(sequencer);
+        let deadline = Instant::now() + config.shutdown_timeout;
+
+        // Closing all inputs and sources lets every dataflow run to
+        // completion, including sinks, which flush whatever they have
+        // received.
+        server.internal.close_inputs();
+        server.close_sources();
 
         // Shutdown loggers s.t. logging dataflows can shut down.
         #[cfg(feature = "real-time")]
         server.shutdown_logging(worker).unwrap();
 
+        while !server.probe.done() && Instant::now() < deadline {
+            refuse_commands(&mut io, &mut sequencer, next_tx, &server.interests);
+
+            // Sources only notice they have been closed once they
+            // are scheduled. Ticks don't matter anymore.
+            while let Some(activator) = server.scheduler.borrow_mut().realtime.next() {
+                activator.schedule();
+            }
+
+            worker.step_or_park(Some(Duration::from_millis(10)));
+        }
+
+        if !server.probe.done() {
+            warn!("[W{}] dataflows did not complete within {:?}", worker.index(), config.shutdown_timeout);
+        }
+
+        for client in io.clients().into_iter().chain(io.streams()) {
+            let message = serde_json::json!({
+                "category": "df/shutdown",
+                "message": "The server is shutting down.",
+            });
+
+            io.send.send(Output::Message(client.into(), message)).unwrap();
+        }
+
+        // Give final messages a chance to go out.
+        let grace = std::cmp::min(deadline, Instant::now() + Duration::from_millis(500));
+        while Instant::now() < grace {
+            refuse_commands(&mut io, &mut sequencer, next_tx, &server.interests);
+            worker.step_or_park(Some(Duration::from_millis(10)));
+        }
+
+        drop(sequencer);
+
     }).expect("Timely computation did not exit cleanly");
 }
@@ -14,22 +14,68 @@ use ws::connection::{ConnEvent, Connection};
 use declarative_dataflow::server::Request;
 use declarative_dataflow::{Error, Output};
 
+use crate::auth::{Permissions, Policy};
+use crate::http::{is_http, Http, HttpEvent, HTTP_SERVER};
 use crate::Aid;
 
 const SERVER: Token = Token(std::usize::MAX - 1);
 const RESULTS: Token = Token(std::usize::MAX - 2);
 pub const SYSTEM: Token = Token(std::usize::MAX - 3);
+const DIRECT: Token = Token(std::usize::MAX - 5);
 
 /// A high-level event devoid of I/O details.
 pub enum DomainEvent {
     /// A client sent one or more requests.
     Requests(Token, Vec<Request<Aid>>),
+    /// A client's message was refused before it could be sequenced,
+    /// e.g. for lack of permissions.
+    Refused(Token, Error),
     /// A client has went away.
     Disconnect(Token),
 }
 
 use DomainEvent::*;
 
+/// Requests concerning a connection itself, rather than the
+/// domain. Clients send these as messages of their own, instead of
+/// as part of a batch. They are handled right here and never reach
+/// the sequencer, but are acknowledged like an empty batch.
+#[derive(Deserialize, Debug)]
+pub enum ConnectionRequest {
+    /// Authenticates the connection with the given secret.
+    Authenticate(String),
+    /// Asks for a `df/handled` message after each of the
+    /// connection's batches, including this one. HTTP connections
+    /// always receive these.
+    AcknowledgeBatches,
+}
+
+/// A message received over a websocket connection.
+enum Incoming {
+    /// Requests to be sequenced.
+    Batch(Vec<Request<Aid>>),
+    /// A request concerning the connection itself.
+    Connection(ConnectionRequest),
+}
+
+/// Wire formats understood by the server. Each connection starts out
+/// speaking json and switches to MessagePack as soon as the client
+/// sends a binary frame. Outputs are always encoded in the format
+/// the connection last sent requests in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    /// JSON over text frames.
+    Json,
+    /// MessagePack over binary frames.
+    MessagePack,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Json
+    }
+}
+
 /// State for translating low-level I/O events into domain events.
 pub struct IO {
     // Event loop.
@@ -44,20 +90,41 @@ pub struct IO {
     pub send: channel::Sender<Output>,
     /// Receive handle to internal channel.
     pub recv: channel::Receiver<Output>,
+    /// Input handle to internal channel for outputs addressed to a
+    /// single client, regardless of what it is interested in.
+    pub send_direct: channel::Sender<(Token, Output)>,
+    /// Receive handle to internal channel for addressed outputs.
+    pub recv_direct: channel::Receiver<(Token, Output)>,
     // TCP server socket.
     server_socket: TcpListener,
     // Client connections.
     connections: Slab<Connection>,
     next_connection_id: u32,
+    // Wire format chosen by each client.
+    encodings: HashMap<Token, Encoding>,
+    // Access control policy. None allows everything.
+    policy: Option<Policy>,
+    // Permissions of authenticated clients.
+    permissions: HashMap<Token, Permissions>,
+    // Websocket clients that asked for their batches to be
+    // acknowledged.
+    acknowledged: HashSet<Token>,
+    // Plain HTTP frontend, if enabled.
+    http: Option<Http>,
     // WebSocket settings.
     ws_settings: ws::Settings,
 }
 
 impl IO {
-    pub fn new(address: SocketAddr) -> Self {
+    pub fn new(
+        address: SocketAddr,
+        http_address: Option<SocketAddr>,
+        policy: Option<Policy>,
+    ) -> Self {
         let poll = Poll::new().expect("failed to setup event loop");
 
         let (send, recv) = channel::channel::<Output>();
+        let (send_direct, recv_direct) = channel::channel::<(Token, Output)>();
 
         let server_socket = TcpListener::bind(&address).expect("failed to create server socket");
 
@@ -69,9 +136,19 @@ impl IO {
         )
         .expect("failed to register result channel");
 
+        poll.register(
+            &recv_direct,
+            DIRECT,
+            Ready::readable(),
+            PollOpt::edge() | PollOpt::oneshot(),
+        )
+        .expect("failed to register direct result channel");
+
         poll.register(&server_socket, SERVER, Ready::readable(), PollOpt::level())
             .expect("failed to register server socket");
 
+        let http = http_address.map(|http_address| Http::new(http_address, &poll));
+
         let ws_settings = ws::Settings {
             max_connections: 1024,
             ..ws::Settings::default()
@@ -84,15 +161,72 @@ impl IO {
             domain_events: VecDeque::new(),
             send,
             recv,
+            send_direct,
+            recv_direct,
             server_socket,
             connections: Slab::with_capacity(ws_settings.max_connections),
             next_connection_id: 0,
+            encodings: HashMap::new(),
+            policy,
+            permissions: HashMap::new(),
+            acknowledged: HashSet::new(),
+            http,
             ws_settings,
         }
     }
 
+    /// Returns the number of open client connections, over both
+    /// websockets and HTTP.
+    pub fn connection_count(&self) -> usize {
+        let http = match self.http {
+            None => 0,
+            Some(ref http) => http.connection_count(),
+        };
+
+        self.connections.len() + http
+    }
+
+    /// Returns the tokens of all open websocket connections.
+    pub fn clients(&self) -> Vec<Token> {
+        self.connections.iter().map(|(key, _conn)| Token(key)).collect()
+    }
+
+    /// Returns the tokens of all HTTP connections streaming results.
+    pub fn streams(&self) -> Vec<Token> {
+        match self.http {
+            None => Vec::new(),
+            Some(ref http) => http.streams(),
+        }
+    }
+
+    /// Returns the permissions of the client behind the given token,
+    /// or None if no policy is in place.
+    pub fn permissions(&self, token: Token) -> Option<Permissions> {
+        let policy = self.policy.as_ref()?;
+
+        let permissions = self
+            .permissions
+            .get(&token)
+            .or_else(|| policy.anonymous.as_ref())
+            .cloned()
+            .unwrap_or_else(|| Permissions {
+                requests: Some(HashSet::new()),
+                read: Some(Vec::new()),
+                write: Some(Vec::new()),
+                files: None,
+            });
+
+        Some(permissions)
+    }
+
+    /// Returns true iff the client behind the given token wants to
+    /// know when each of its batches has been handled.
+    pub fn acknowledges(&self, token: Token) -> bool {
+        is_http(token) || self.acknowledged.contains(&token)
+    }
+
     /// Handle networking events.
-    pub fn step(&mut self, t: u64, interests: &HashMap<String, HashSet<Token>>) {
+    pub fn step(&mut self, interests: &HashMap<String, HashSet<Token>>) {
         // We mustn't timeout here, we are not in charge of blocking.
         self.poll
             .poll(&mut self.events, Some(Duration::from_millis(0)))
@@ -144,47 +278,112 @@ impl IO {
                         }
                     }
                 }
-                RESULTS => {
-                    while let Ok(out) = self.recv.try_recv() {
-                        let tokens: Box<dyn Iterator<Item = Token>> = match &out {
-                            &Output::QueryDiff(ref name, ref results) => {
-                                info!("[IO] {} {} results", name, results.len());
-
-                                match interests.get(name) {
-                                    None => {
-                                        warn!("result on query {} w/o interested clients", name);
-                                        Box::new(std::iter::empty())
+                RESULTS | DIRECT => {
+                    loop {
+                        let (target, out) = match self.recv_direct.try_recv() {
+                            Ok((token, out)) => (Some(token), out),
+                            Err(_) => match self.recv.try_recv() {
+                                Ok(out) => (None, out),
+                                Err(_) => break,
+                            },
+                        };
+
+                        let tokens: Box<dyn Iterator<Item = Token>> = match target {
+                            Some(token) => Box::new(std::iter::once(token)),
+                            None => match &out {
+                                &Output::QueryDiff(ref name, ref results) => {
+                                    info!("[IO] {} {} results", name, results.len());
+
+                                    match interests.get(name) {
+                                        None => {
+                                            warn!("result on query {} w/o interested clients", name);
+                                            Box::new(std::iter::empty())
+                                        }
+                                        Some(tokens) => Box::new(tokens.iter().cloned()),
                                     }
-                                    Some(tokens) => Box::new(tokens.iter().cloned()),
                                 }
-                            }
-                            &Output::Json(ref name, _, _, _) => {
-                                info!("[IO] json on query {}", name);
+                                &Output::Complete(ref name, ref time) => {
+                                    trace!("[IO] {} complete at {:?}", name, time);
 
-                                match interests.get(name) {
-                                    None => {
-                                        warn!("result on query {} w/o interested clients", name);
-                                        Box::new(std::iter::empty())
+                                    match interests.get(name) {
+                                        None => Box::new(std::iter::empty()),
+                                        Some(tokens) => Box::new(tokens.iter().cloned()),
                                     }
-                                    Some(tokens) => Box::new(tokens.iter().cloned()),
                                 }
-                            }
-                            &Output::Message(client, ref msg) => {
-                                info!("[IO] {:?}", msg);
-                                Box::new(std::iter::once(client.into()))
-                            }
-                            &Output::Error(client, ref error, _) => {
-                                error!("[IO] {:?}", error);
-                                Box::new(std::iter::once(client.into()))
-                            }
-                        };
+                                &Output::Progress(ref name, ref frontier) => {
+                                    trace!("[IO] {} progressed to {:?}", name, frontier);
 
-                        let serialized = serde_json::to_string::<Output>(&out)
-                            .expect("failed to serialize output");
+                                    match interests.get(name) {
+                                        None => Box::new(std::iter::empty()),
+                                        Some(tokens) => Box::new(tokens.iter().cloned()),
+                                    }
+                                }
+                                &Output::Snapshot(client, ref name, _, ref results) => {
+                                    info!("[IO] {} snapshot with {} results", name, results.len());
+                                    Box::new(std::iter::once(client.into()))
+                                }
+                                &Output::TxReport(client, tx, ref time) => {
+                                    trace!("[IO] tx {} inserted at {:?}", tx, time);
+                                    Box::new(std::iter::once(client.into()))
+                                }
+                                &Output::TxVisible(client, tx) => {
+                                    trace!("[IO] tx {} visible", tx);
+                                    Box::new(std::iter::once(client.into()))
+                                }
+                                &Output::Json(ref name, _, _, _) => {
+                                    info!("[IO] json on query {}", name);
 
-                        let msg = ws::Message::text(serialized);
+                                    match interests.get(name) {
+                                        None => {
+                                            warn!("result on query {} w/o interested clients", name);
+                                            Box::new(std::iter::empty())
+                                        }
+                                        Some(tokens) => Box::new(tokens.iter().cloned()),
+                                    }
+                                }
+                                &Output::Message(client, ref msg) => {
+                                    info!("[IO] {:?}", msg);
+                                    Box::new(std::iter::once(client.into()))
+                                }
+                                &Output::Error(client, ref error, _) => {
+                                    error!("[IO] {:?}", error);
+                                    Box::new(std::iter::once(client.into()))
+                                }
+                            },
+                        };
+
+                        // Outputs are encoded at most once per format.
+                        let mut text = None;
+                        let mut binary = None;
 
                         for token in tokens {
+                            if is_http(token) {
+                                if let Some(ref mut http) = self.http {
+                                    http.send(&self.poll, token, &out);
+                                }
+
+                                continue;
+                            }
+
+                            let msg = match self.encodings.get(&token).cloned().unwrap_or_default() {
+                                Encoding::Json => text
+                                    .get_or_insert_with(|| {
+                                        let serialized = serde_json::to_string::<Output>(&out)
+                                            .expect("failed to serialize output");
+
+                                        ws::Message::text(serialized)
+                                    })
+                                    .clone(),
+                                Encoding::MessagePack => binary
+                                    .get_or_insert_with(|| {
+                                        let serialized = rmp_serde::to_vec_named(&out)
+                                            .expect("failed to serialize output");
+
+                                        ws::Message::binary(serialized)
+                                    })
+                                    .clone(),
+                            };
+
                             match self.connections.get_mut(token.into()) {
                                 None => {
                                     // @TODO we need to clean up the connection here
@@ -192,7 +391,7 @@ impl IO {
                                     self.domain_events.push_back(Disconnect(token));
                                 }
                                 Some(conn) => {
-                                    conn.send_message(msg.clone())
+                                    conn.send_message(msg)
                                         .expect("failed to send message");
 
                                     self.poll
@@ -216,6 +415,54 @@ impl IO {
                             PollOpt::edge() | PollOpt::oneshot(),
                         )
                         .unwrap();
+
+                    self.poll
+                        .reregister(
+                            &self.recv_direct,
+                            DIRECT,
+                            Ready::readable(),
+                            PollOpt::edge() | PollOpt::oneshot(),
+                        )
+                        .unwrap();
+                }
+                HTTP_SERVER => {
+                    if let Some(ref mut http) = self.http {
+                        http.accept(&self.poll);
+                    }
+                }
+                token if is_http(token) => {
+                    if let Some(ref mut http) = self.http {
+                        for http_event in http.ready(&self.poll, token, event.readiness()) {
+                            match http_event {
+                                HttpEvent::Requests(token, secret, requests) => {
+                                    let authenticated = match secret {
+                                        None => Ok(()),
+                                        Some(secret) => authenticate(&self.policy, &mut self.permissions, token, &secret),
+                                    };
+
+                                    let authorized = match authenticated {
+                                        Err(error) => Err(error),
+                                        Ok(()) => authorize(&self.policy, &self.permissions, token, requests),
+                                    };
+
+                                    match authorized {
+                                        Err(error) => http.reject(&self.poll, token, &error),
+                                        Ok(requests) => {
+                                            // Even empty batches are sequenced, as
+                                            // HTTP clients wait for theirs to be
+                                            // handled.
+                                            http.authorized(&self.poll, token);
+                                            self.domain_events.push_back(Requests(token, requests));
+                                        }
+                                    }
+                                }
+                                HttpEvent::Disconnect(token) => {
+                                    self.permissions.remove(&token);
+                                    self.domain_events.push_back(Disconnect(token));
+                                }
+                            }
+                        }
+                    }
                 }
                 _ => {
                     let token = event.token();
@@ -250,26 +497,53 @@ impl IO {
                            match conn_event {
                                 ConnEvent::Message(msg) => {
                                     trace!("[WS] ConnEvent::Message");
-                                    match msg {
+                                    let parsed = match msg {
                                         ws::Message::Text(string) => {
-                                            match serde_json::from_str::<Vec<Request<Aid>>>(&string) {
-                                                Err(serde_error) => {
-                                                    self.send
-                                                        .send(Output::Error(
-                                                            token.into(),
-                                                            Error::incorrect(serde_error),
-                                                            t,
-                                                        ))
-                                                        .unwrap();
-                                                }
-                                                Ok(requests) => {
-                                                    self.domain_events
-                                                        .push_back(Requests(token, requests));
-                                                }
+                                            self.encodings.insert(token, Encoding::Json);
+                                            serde_json::from_str::<Vec<Request<Aid>>>(&string)
+                                                .map(Incoming::Batch)
+                                                .or_else(|error| {
+                                                    serde_json::from_str::<ConnectionRequest>(&string)
+                                                        .map(Incoming::Connection)
+                                                        .map_err(|_| Error::incorrect(error))
+                                                })
+                                        }
+                                        ws::Message::Binary(bytes) => {
+                                            self.encodings.insert(token, Encoding::MessagePack);
+                                            rmp_serde::from_slice::<Vec<Request<Aid>>>(&bytes)
+                                                .map(Incoming::Batch)
+                                                .or_else(|error| {
+                                                    rmp_serde::from_slice::<ConnectionRequest>(&bytes)
+                                                        .map(Incoming::Connection)
+                                                        .map_err(|_| Error::incorrect(error))
+                                                })
+                                        }
+                                    };
+
+                                    // Refusals are sequenced as well, s.t.
+                                    // they are reported under a tx of their
+                                    // own, just like any other error.
+                                    let event = match parsed {
+                                        Err(error) => Refused(token, error),
+                                        Ok(Incoming::Connection(ConnectionRequest::Authenticate(secret))) => {
+                                            match authenticate(&self.policy, &mut self.permissions, token, &secret) {
+                                                Err(error) => Refused(token, error),
+                                                Ok(()) => Requests(token, Vec::new()),
                                             }
                                         }
-                                        ws::Message::Binary(_) => unimplemented!(),
-                                    }
+                                        Ok(Incoming::Connection(ConnectionRequest::AcknowledgeBatches)) => {
+                                            self.acknowledged.insert(token);
+                                            Requests(token, Vec::new())
+                                        }
+                                        Ok(Incoming::Batch(requests)) => {
+                                            match authorize(&self.policy, &self.permissions, token, requests) {
+                                                Err(error) => Refused(token, error),
+                                                Ok(requests) => Requests(token, requests),
+                                            }
+                                        }
+                                    };
+
+                                    self.domain_events.push_back(event);
                                 }
                                 ConnEvent::Close(code, reason) => {
                                     trace!("[WS] ConnEvent::Close");
@@ -295,6 +569,9 @@ impl IO {
                     if !active {
                         self.domain_events.push_back(Disconnect(token.clone()));
                         self.connections.remove(token.into());
+                        self.encodings.remove(&token);
+                        self.permissions.remove(&token);
+                        self.acknowledged.remove(&token);
                     } else {
                         let conn = &self.connections[token.into()];
                         self.poll
@@ -312,6 +589,48 @@ impl IO {
     }
 }
 
+/// Grants a connection the permissions associated with the given
+/// secret. Without a policy, there is nothing to grant.
+fn authenticate(
+    policy: &Option<Policy>,
+    permissions: &mut HashMap<Token, Permissions>,
+    token: Token,
+    secret: &str,
+) -> Result<(), Error> {
+    if let Some(policy) = policy {
+        let granted = policy.authenticate(secret)?;
+        permissions.insert(token, granted);
+    }
+
+    Ok(())
+}
+
+/// Checks requests against the policy, before they can reach the
+/// sequencer. A batch is rejected as a whole, if any of its requests
+/// is denied.
+fn authorize(
+    policy: &Option<Policy>,
+    permissions: &HashMap<Token, Permissions>,
+    token: Token,
+    requests: Vec<Request<Aid>>,
+) -> Result<Vec<Request<Aid>>, Error> {
+    let policy = match policy {
+        None => return Ok(requests),
+        Some(policy) => policy,
+    };
+
+    for req in requests.iter() {
+        match permissions.get(&token).or_else(|| policy.anonymous.as_ref()) {
+            None => {
+                return Err(Error::forbidden("Authentication required."));
+            }
+            Some(permissions) => permissions.check(req)?,
+        }
+    }
+
+    Ok(requests)
+}
+
 impl Iterator for IO {
     type Item = DomainEvent;
     fn next(&mut self) -> Option<DomainEvent> {
@@ -26,3 +26,39 @@ fn test_serialization() {
         "{\"Uuid\":\"71828aae-4fc8-421b-82ca-68c5f4981d74\"}".to_string(),
     );
 }
+
+#[test]
+fn test_binary_compatibility() {
+    use declarative_dataflow::server::Request;
+    use declarative_dataflow::timestamp::Time;
+    use declarative_dataflow::{Datom, Output};
+
+    let requests: Vec<Request<std::string::String>> = vec![
+        Request::Transact(vec![
+            Datom::add(1, ":name", String("Alice".to_string())),
+            Datom::retract(2, ":age", Number(42)),
+        ]),
+        Request::Subscribe(":name".to_string()),
+    ];
+
+    // Requests must survive both encodings, and decode to the same
+    // thing either way.
+    let text = to_string(&requests).unwrap();
+    let binary = rmp_serde::to_vec_named(&requests).unwrap();
+
+    let from_text: Vec<Request<std::string::String>> = serde_json::from_str(&text).unwrap();
+    let from_binary: Vec<Request<std::string::String>> = rmp_serde::from_slice(&binary).unwrap();
+
+    assert_eq!(from_text, requests);
+    assert_eq!(from_binary, requests);
+
+    let output = Output::QueryDiff(
+        "q".to_string(),
+        vec![(vec![Aid(":edge".to_string()), Bool(true)], Time::TxId(1), -1)],
+    );
+
+    let binary = rmp_serde::to_vec_named(&output).unwrap();
+    let from_binary: Output = rmp_serde::from_slice(&binary).unwrap();
+
+    assert_eq!(to_string(&from_binary).unwrap(), to_string(&output).unwrap());
+}
@@ -1,7 +1,7 @@
 //! Logic for working with attributes under a shared timestamp
 //! semantics.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::{Add, AddAssign};
 
 use timely::dataflow::operators::unordered_input::UnorderedHandle;
@@ -13,9 +13,11 @@ use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::operators::arrange::Arrange;
-use differential_dataflow::trace::TraceReader;
+use differential_dataflow::trace::{BatchReader, TraceReader};
 use differential_dataflow::{AsCollection, Collection};
 
+use crate::logging::{Logger, TraceSizeEvent, TxBatchEvent};
+use crate::plan::{Dependencies, Implementable};
 use crate::{AsAid, Datom, Error, Rewind, Rule, Value};
 use crate::{AttributeConfig, QuerySupport};
 use crate::{ShutdownHandle, TraceKeyHandle, TraceValHandle};
@@ -82,6 +84,10 @@ where
     pub rules: HashMap<A, Rule<A>>,
     /// Mapping from query names to their shutdown handles.
     pub shutdown_handles: HashMap<String, ShutdownHandle>,
+    /// Logger for declarative events, if logging is enabled.
+    logger: Option<Logger>,
+    /// Trace sizes as last reported to the logger.
+    reported_sizes: HashMap<A, usize>,
 }
 
 // We're defining domain composition here.
@@ -136,6 +142,12 @@ where
 
         self.shutdown_handles
             .extend(other.shutdown_handles.into_iter());
+
+        if self.logger.is_none() {
+            self.logger = other.logger;
+        }
+
+        self.reported_sizes.extend(other.reported_sizes.into_iter());
     }
 }
 
@@ -176,6 +188,8 @@ where
             reverse_validate: HashMap::new(),
             rules: HashMap::new(),
             shutdown_handles: HashMap::new(),
+            logger: None,
+            reported_sizes: HashMap::new(),
         }
     }
 
@@ -197,20 +211,33 @@ where
             reverse_validate: HashMap::new(),
             rules: HashMap::new(),
             shutdown_handles: HashMap::new(),
+            logger: None,
+            reported_sizes: HashMap::new(),
         }
     }
 
     /// Transact data into one or more inputs.
     pub fn transact(&mut self, tx_data: Vec<Datom<A>>) -> Result<(), Error> {
+        if let Some(ref logger) = self.logger {
+            logger.log(TxBatchEvent {
+                size: tx_data.len() as i64,
+            });
+        }
+
+        // Transactions are applied entirely or not at all.
+        if let Some(Datom(_e, a, _v, _t, _diff)) = tx_data
+            .iter()
+            .find(|datom| !self.input_sessions.contains_key(&datom.1))
+        {
+            return Err(Error::not_found(format!("Attribute {} does not exist.", a)));
+        }
+
         for Datom(e, a, v, t, diff) in tx_data {
-            match self.input_sessions.get_mut(&a) {
-                None => {
-                    return Err(Error::not_found(format!("Attribute {} does not exist.", a)));
-                }
-                Some(handle) => match t {
-                    None => handle.update((e, v), diff),
-                    Some(t) => handle.update_at((e, v), t.into(), diff),
-                },
+            let handle = self.input_sessions.get_mut(&a).expect("attribute vanished");
+
+            match t {
+                None => handle.update((e, v), diff),
+                Some(t) => handle.update_at((e, v), t.into(), diff),
             }
         }
 
@@ -228,6 +255,84 @@ where
         }
     }
 
+    /// Closes and drops all inputs, e.g. before shutting down.
+    pub fn close_inputs(&mut self) {
+        for (_name, handle) in self.input_sessions.drain() {
+            handle.close();
+        }
+    }
+
+    /// Removes a rule, unless other rules still depend on it.
+    pub fn unregister(&mut self, name: &A) -> Result<Rule<A>, Error> {
+        if !self.rules.contains_key(name) {
+            return Err(Error::not_found(format!("Rule {} does not exist.", name)));
+        }
+
+        let dependents = self.dependents(|dependencies| dependencies.names.contains(name));
+        if !dependents.is_empty() {
+            return Err(Error::conflict(format!(
+                "Rule {} is still used by {:?}.",
+                name, dependents
+            )));
+        }
+
+        Ok(self.rules.remove(name).unwrap())
+    }
+
+    /// Closes the input of an attribute (if it has one) and releases
+    /// all of its traces, unless rules still depend on it. Sourced
+    /// attributes will keep receiving inputs, but their arrangements
+    /// are free to compact away.
+    pub fn drop_attribute(&mut self, name: &A) -> Result<(), Error> {
+        if !self.attributes.contains_key(name) {
+            return Err(Error::not_found(format!(
+                "Attribute {} does not exist.",
+                name
+            )));
+        }
+
+        let dependents = self.dependents(|dependencies| dependencies.attributes.contains(name));
+        if !dependents.is_empty() {
+            return Err(Error::conflict(format!(
+                "Attribute {} is still used by {:?}.",
+                name, dependents
+            )));
+        }
+
+        if let Some(handle) = self.input_sessions.remove(name) {
+            handle.close();
+        }
+
+        self.attributes.remove(name);
+
+        self.forward_count.remove(name);
+        self.forward_propose.remove(name);
+        self.forward_validate.remove(name);
+        self.reverse_count.remove(name);
+        self.reverse_propose.remove(name);
+        self.reverse_validate.remove(name);
+        self.reported_sizes.remove(name);
+
+        Ok(())
+    }
+
+    /// Returns the names of all rules whose direct dependencies
+    /// satisfy the given predicate.
+    fn dependents<F>(&self, predicate: F) -> Vec<A>
+    where
+        F: Fn(&Dependencies<A>) -> bool,
+    {
+        let mut dependents: Vec<A> = self
+            .rules
+            .values()
+            .filter(|rule| predicate(&rule.plan.dependencies()))
+            .map(|rule| rule.name.clone())
+            .collect();
+
+        dependents.sort();
+        dependents
+    }
+
     /// Advances the domain to the current domain frontier, thus
     /// allowing traces to compact. All domain input handles are
     /// forwarded up to the frontier, so as not to stall progress.
@@ -286,6 +391,28 @@ where
         }
     }
 
+    /// Returns true if the traces of all given attributes still
+    /// distinguish times in advance of `time`, s.t. updates since
+    /// then can be told apart from earlier ones. Only attributes
+    /// configured with a trace slack are ever compacted.
+    pub fn distinguishes(&self, attributes: &HashSet<A>, time: &T) -> bool {
+        attributes.iter().all(|aid| match self.attributes.get(aid) {
+            None => false,
+            Some(config) => match config.trace_slack {
+                None => true,
+                Some(ref trace_slack) => {
+                    let compacted = self
+                        .last_advance
+                        .iter()
+                        .map(|t| t.rewind(trace_slack.clone().into()))
+                        .collect::<Vec<T>>();
+
+                    AntichainRef::new(&compacted).less_equal(time)
+                }
+            },
+        })
+    }
+
     /// Advances domain traces up to the specified frontier minus
     /// their configured slack.
     pub fn advance_traces(&mut self, frontier: &[T]) -> Result<(), Error> {
@@ -335,11 +462,81 @@ where
                     }
                 }
             }
+
+            if self.logger.is_some() {
+                self.report_trace_sizes();
+            }
         }
 
         Ok(())
     }
 
+    /// Logs the trace sizes of all attributes whose traces changed
+    /// since they were last reported.
+    fn report_trace_sizes(&mut self) {
+        let names: Vec<A> = self.attributes.keys().cloned().collect();
+
+        for name in names.into_iter() {
+            let size = self.trace_size(&name);
+
+            if self.reported_sizes.get(&name) != Some(&size) {
+                if let Some(ref logger) = self.logger {
+                    logger.log(TraceSizeEvent {
+                        name: name.to_string(),
+                        size: size as i64,
+                    });
+                }
+
+                self.reported_sizes.insert(name, size);
+            }
+        }
+    }
+
+    /// Reports the number of updates currently held across all
+    /// traces maintained for the specified attribute.
+    pub fn trace_size(&mut self, name: &A) -> usize {
+        let mut size = 0;
+
+        if let Some(trace) = self.forward_count.get_mut(name) {
+            trace.map_batches(|batch| size += batch.len());
+        }
+
+        if let Some(trace) = self.forward_propose.get_mut(name) {
+            trace.map_batches(|batch| size += batch.len());
+        }
+
+        if let Some(trace) = self.forward_validate.get_mut(name) {
+            trace.map_batches(|batch| size += batch.len());
+        }
+
+        if let Some(trace) = self.reverse_count.get_mut(name) {
+            trace.map_batches(|batch| size += batch.len());
+        }
+
+        if let Some(trace) = self.reverse_propose.get_mut(name) {
+            trace.map_batches(|batch| size += batch.len());
+        }
+
+        if let Some(trace) = self.reverse_validate.get_mut(name) {
+            trace.map_batches(|batch| size += batch.len());
+        }
+
+        size
+    }
+
+    /// Installs a logger for declarative events. Passing `None`
+    /// releases any previously installed logger.
+    pub fn set_logger(&mut self, logger: Option<Logger>) {
+        self.logger = logger;
+        self.reported_sizes.clear();
+    }
+
+    /// Returns the logger for declarative events, if logging is
+    /// enabled.
+    pub fn logger(&self) -> Option<&Logger> {
+        self.logger.as_ref()
+    }
+
     /// Returns a handle to the domain's input probe.
     pub fn domain_probe(&self) -> &ProbeHandle<T> {
         &self.domain_probe
@@ -383,6 +580,12 @@ where
         self.attributes.contains_key(name)
     }
 
+    /// Checks whether an input of that name exists, i.e. whether the
+    /// attribute can be transacted upon.
+    pub fn has_input(&self, name: &A) -> bool {
+        self.input_sessions.contains_key(name)
+    }
+
     /// Retrieves the forward count trace for the specified aid.
     pub fn forward_count(&mut self, name: &A) -> Option<&mut TraceKeyHandle<Value, T, isize>> {
         self.forward_count.get_mut(name)
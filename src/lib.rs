@@ -143,7 +143,17 @@ impl std::convert::From<Value> for serde_json::Value {
             Value::String(v) => serde_json::Value::String(v),
             Value::Bool(v) => serde_json::Value::Bool(v),
             Value::Number(v) => serde_json::Value::Number(serde_json::Number::from(v)),
-            _ => unimplemented!(),
+            Value::Rational32(v) => {
+                serde_json::Number::from_f64(f64::from(*v.numer()) / f64::from(*v.denom()))
+                    .map(serde_json::Value::Number)
+                    .unwrap_or(serde_json::Value::Null)
+            }
+            Value::Instant(v) => serde_json::Value::Number(serde_json::Number::from(v)),
+            Value::Uuid(v) => serde_json::Value::String(v.to_string()),
+            #[cfg(feature = "real")]
+            Value::Real(v) => serde_json::Number::from_f64(v.to_float::<f64>())
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
         }
     }
 }
@@ -159,7 +169,7 @@ impl std::convert::From<Value> for Eid {
 }
 
 /// A client-facing, non-exceptional error.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct Error {
     /// Error category.
     #[serde(rename = "df.error/category")]
@@ -194,6 +204,14 @@ impl Error {
         }
     }
 
+    /// Fix client permissions.
+    pub fn forbidden<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/forbidden".to_string(),
+            message: error.to_string(),
+        }
+    }
+
     /// Fix worker bug.
     pub fn fault<E: std::string::ToString>(error: E) -> Error {
         Error {
@@ -209,6 +227,14 @@ impl Error {
             message: error.to_string(),
         }
     }
+
+    /// Start over from a fresh snapshot.
+    pub fn snapshot_required<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/snapshot-required".to_string(),
+            message: error.to_string(),
+        }
+    }
 }
 
 /// Transaction data.
@@ -251,6 +277,20 @@ pub enum Output {
     /// A batch of (tuple, time, diff) triples as returned by Datalog
     /// queries.
     QueryDiff(String, Vec<ResultDiff<Time>>),
+    /// Marks all results of the named query up to and including the
+    /// given time as sent. Diffs received before this marker form a
+    /// transactionally consistent update.
+    Complete(String, Time),
+    /// The output frontier of the named query has advanced. No more
+    /// results will be sent at times not in advance of it.
+    Progress(String, Vec<Time>),
+    /// A consolidated snapshot of the named query, complete as of the
+    /// given time, sent in response to a one-shot query.
+    Snapshot(Client, String, Time, Vec<ResultDiff<Time>>),
+    /// The transaction id and time assigned to a client's inputs.
+    TxReport(Client, server::TxId, Time),
+    /// The identified transaction is reflected in all results.
+    TxVisible(Client, server::TxId),
     /// A JSON object, e.g. as returned by GraphQL queries.
     #[cfg(feature = "serde_json")]
     Json(String, serde_json::Value, Time, isize),
@@ -8,6 +8,18 @@ pub type Logger = ::timely::logging::Logger<DeclarativeEvent>;
 pub enum DeclarativeEvent {
     /// Tuples materialized during a join.
     JoinTuples(JoinTuplesEvent),
+    /// A rule was implemented as a dataflow.
+    RuleImplemented(RuleImplementedEvent),
+    /// A rule dataflow was shut down.
+    RuleShutdown(RuleShutdownEvent),
+    /// Hector picked extenders for a stage of a delta pipeline.
+    ExtenderChosen(ExtenderChosenEvent),
+    /// Tuples materialized at a stage of a delta pipeline.
+    StageTuples(StageTuplesEvent),
+    /// The current size of an attribute's traces.
+    TraceSize(TraceSizeEvent),
+    /// A batch of datoms was transacted.
+    TxBatch(TxBatchEvent),
 }
 
 /// Tuples materialized during a join.
@@ -22,3 +34,91 @@ impl From<JoinTuplesEvent> for DeclarativeEvent {
         DeclarativeEvent::JoinTuples(e)
     }
 }
+
+/// A rule was implemented as a dataflow.
+#[derive(Debug, Clone, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct RuleImplementedEvent {
+    /// The name of the rule.
+    pub name: String,
+}
+
+impl From<RuleImplementedEvent> for DeclarativeEvent {
+    fn from(e: RuleImplementedEvent) -> Self {
+        DeclarativeEvent::RuleImplemented(e)
+    }
+}
+
+/// A rule dataflow was shut down.
+#[derive(Debug, Clone, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct RuleShutdownEvent {
+    /// The name of the rule.
+    pub name: String,
+}
+
+impl From<RuleShutdownEvent> for DeclarativeEvent {
+    fn from(e: RuleShutdownEvent) -> Self {
+        DeclarativeEvent::RuleShutdown(e)
+    }
+}
+
+/// Hector picked an extender for a stage of a delta pipeline.
+#[derive(Debug, Clone, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct ExtenderChosenEvent {
+    /// The attribute driving the delta pipeline.
+    pub source_attribute: String,
+    /// The variable the prefix is being extended to.
+    pub target: u32,
+    /// A description of the binding backing the extender.
+    pub extender: String,
+}
+
+impl From<ExtenderChosenEvent> for DeclarativeEvent {
+    fn from(e: ExtenderChosenEvent) -> Self {
+        DeclarativeEvent::ExtenderChosen(e)
+    }
+}
+
+/// Tuples materialized at a stage of a delta pipeline.
+#[derive(Debug, Clone, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct StageTuplesEvent {
+    /// The attribute driving the delta pipeline.
+    pub source_attribute: String,
+    /// The position of the stage within the pipeline.
+    pub stage: usize,
+    /// How many tuples.
+    pub cardinality: i64,
+}
+
+impl From<StageTuplesEvent> for DeclarativeEvent {
+    fn from(e: StageTuplesEvent) -> Self {
+        DeclarativeEvent::StageTuples(e)
+    }
+}
+
+/// The current size of an attribute's traces.
+#[derive(Debug, Clone, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct TraceSizeEvent {
+    /// The name of the attribute.
+    pub name: String,
+    /// Number of updates held across all of the attribute's traces.
+    pub size: i64,
+}
+
+impl From<TraceSizeEvent> for DeclarativeEvent {
+    fn from(e: TraceSizeEvent) -> Self {
+        DeclarativeEvent::TraceSize(e)
+    }
+}
+
+/// A batch of datoms was transacted.
+#[derive(Debug, Clone, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct TxBatchEvent {
+    /// How many datoms.
+    pub size: i64,
+}
+
+impl From<TxBatchEvent> for DeclarativeEvent {
+    fn from(e: TxBatchEvent) -> Self {
+        DeclarativeEvent::TxBatch(e)
+    }
+}
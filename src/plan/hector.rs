@@ -30,7 +30,7 @@ use differential_dataflow::{AsCollection, Collection, ExchangeData, Hashable};
 use crate::binding::{AsBinding, BinaryPredicate, Binding};
 use crate::binding::{BinaryPredicateBinding, ConstantBinding};
 use crate::domain::Domain;
-use crate::logging::DeclarativeEvent;
+use crate::logging::{DeclarativeEvent, ExtenderChosenEvent, StageTuplesEvent};
 use crate::plan::{Dependencies, Implementable};
 use crate::timestamp::{altneu::AltNeu, Rewind};
 use crate::{AsAid, Value, Var};
@@ -682,6 +682,14 @@ where
 
                                             debug!("\t...using {:?}", other);
 
+                                            if let Some(ref logger) = logger {
+                                                logger.log(ExtenderChosenEvent {
+                                                    source_attribute: delta_binding.source_attribute.to_string(),
+                                                    target: *target,
+                                                    extender: format!("{:?}", other),
+                                                });
+                                            }
+
                                             match other {
                                                 Binding::Not(_other) => {
                                                     // Due to the way we enqueued the bindings above, we can now
@@ -866,23 +874,32 @@ where
                                             }
                                         }
 
+                                        let stage = prefix.len();
                                         prefix.push(*target);
 
                                         // @TODO impl ProposeExtensionMethod for Arranged
                                         let extended = source.extend(&mut extenders[..]);
 
-                                        if logger.is_some() {
-                                            let worker_index = scope.index();
+                                        if let Some(ref logger) = logger {
+                                            let logger = logger.clone();
                                             let source_attribute = delta_binding.source_attribute.to_string();
                                             extended
-                                                // .inspect(move |x| { println!("{} extended: {:?}", source_attribute, x); })
                                                 .map(|_| ())
                                                 .consolidate()
                                                 .count()
-                                                .map(move |(_, count)| (Value::Eid(worker_index as u64), Value::Number(count as i64)))
                                                 .leave()
                                                 .leave()
-                                                .inspect(move |x| { println!("{}: {:?}", source_attribute, x); });
+                                                .inspect(move |(((), count), _t, diff)| {
+                                                    // Counts are retracted and re-introduced as
+                                                    // they change, we only report the new ones.
+                                                    if *diff > 0 {
+                                                        logger.log(StageTuplesEvent {
+                                                            source_attribute: source_attribute.clone(),
+                                                            stage,
+                                                            cardinality: *count as i64,
+                                                        });
+                                                    }
+                                                });
                                         }
 
                                         source = extended
@@ -38,6 +38,16 @@ impl RealtimeScheduler {
         }
     }
 
+    /// Returns the number of pending activations and events.
+    pub fn len(&self) -> usize {
+        self.activator_queue.len()
+    }
+
+    /// Returns true iff no activations or events are pending.
+    pub fn is_empty(&self) -> bool {
+        self.activator_queue.is_empty()
+    }
+
     /// Returns the duration until the next activation in
     /// `activator_queue` from `now()`. If no activations are present
     /// returns None.
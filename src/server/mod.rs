@@ -1,36 +1,42 @@
 //! Server logic for driving the library via commands.
 
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::time::{Duration, Instant};
 
 use timely::communication::Allocate;
+use timely::dataflow::channels::pact::{ParallelizationContract, Pipeline};
 use timely::dataflow::operators::capture::event::link::EventLink;
-use timely::dataflow::operators::UnorderedInput;
-use timely::dataflow::{ProbeHandle, Scope};
+use timely::dataflow::operators::generic::{Operator, OutputHandle};
+use timely::dataflow::operators::{Broadcast, Filter, Probe, UnorderedInput};
+use timely::dataflow::{ProbeHandle, Scope, Stream};
 use timely::logging::{BatchLogger, TimelyEvent};
+use timely::order::PartialOrder;
 use timely::progress::Timestamp;
 use timely::worker::Worker;
 
 use differential_dataflow::collection::{AsCollection, Collection};
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::logging::DifferentialEvent;
+use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::Threshold;
+use differential_dataflow::trace::TraceReader;
 use differential_dataflow::ExchangeData;
 
 use crate::domain::{AsSingletonDomain, Domain};
-use crate::logging::DeclarativeEvent;
+use crate::logging::{DeclarativeEvent, RuleImplementedEvent, RuleShutdownEvent};
 use crate::operators::LastWriteWins;
 use crate::scheduling::Scheduler;
-use crate::sinks::Sink;
+use crate::sinks::{drain_complete, Sink};
 use crate::sources::{Source, Sourceable, SourcingContext};
 use crate::Rule;
 use crate::{
-    implement, implement_neu, AttributeConfig, IndexDirection, InputSemantics, ShutdownHandle,
+    collect_dependencies, implement, implement_neu, AttributeConfig, IndexDirection,
+    InputSemantics, QuerySupport, ShutdownHandle, TraceKeyHandle,
 };
-use crate::{AsAid, Datom, Error, Rewind, Time, Value};
+use crate::{AsAid, Datom, Error, ResultDiff, Rewind, Time, Value};
 
 /// Server configuration.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -116,12 +122,31 @@ pub struct Interest {
     pub sink: Option<Sink>,
     /// Whether or not to log events from this dataflow.
     pub disable_logging: Option<bool>,
+    /// Identifies this subscription. Subscriptions with an id are
+    /// served by a dataflow of their own and results are published
+    /// under the id, rather than under the name of the query.
+    pub id: Option<String>,
+    /// Resumes a subscription whose results have been received up to
+    /// and including the given time. Only results at later times are
+    /// sent. Requires an id.
+    pub resume_from: Option<Time>,
+}
+
+impl Interest {
+    /// The name under which results of this subscription are
+    /// published.
+    pub fn key(&self) -> &str {
+        match self.id {
+            None => &self.name,
+            Some(ref id) => id,
+        }
+    }
 }
 
 impl std::convert::From<&Interest> for crate::sinks::SinkingContext {
     fn from(interest: &Interest) -> Self {
         Self {
-            name: interest.name.clone(),
+            name: interest.key().to_string(),
             granularity: interest.granularity.clone(),
         }
     }
@@ -135,6 +160,8 @@ pub struct Register<A: AsAid> {
     pub rules: Vec<Rule<A>>,
     /// The names of rules that should be published.
     pub publish: Vec<A>,
+    /// Whether existing rules of the same name may be redefined.
+    pub replace: Option<bool>,
 }
 
 /// A request with the intent of creating a new named, globally
@@ -153,24 +180,49 @@ pub struct CreateAttribute {
 pub enum Request<A: AsAid + From<&'static str>> {
     /// Sends inputs via one or more registered handles.
     Transact(Vec<Datom<A>>),
+    /// Like `Transact`, but the client is told the transaction id and
+    /// time assigned to its inputs, and is notified once they are
+    /// reflected in all results.
+    TransactReport(Vec<Datom<A>>),
     /// Expresses interest in an entire attribute.
     Subscribe(String),
     /// Derives new attributes under a new namespace.
     #[cfg(feature = "graphql")]
     Derive(String, String),
-    /// Expresses interest in a named relation.
+    /// Expresses interest in a named relation. Results are computed
+    /// once and shared by all interested clients, each of which gets
+    /// them at its own granularity and via its own sink.
     Interest(Interest),
+    /// Requests a single, consolidated snapshot of a named relation,
+    /// as of the specified time or the current epoch. The dataflow is
+    /// shut down once the snapshot has been sent.
+    Query {
+        /// The name of a previously registered dataflow.
+        name: String,
+        /// Only updates at times before this one are included.
+        as_of: Option<Time>,
+    },
     /// Expresses that the interest in a named relation has
     /// stopped. Once all interested clients have sent this, the
-    /// dataflow can be cleaned up.
+    /// dataflow can be cleaned up. Subscriptions with an id are
+    /// referred to by their id.
     Uninterest(String),
     /// Registers one or more named relations.
     Register(Register<A>),
     /// A request with the intent of attaching to an external data
     /// source that publishes one or more attributes and relations.
     RegisterSource(Source<A>),
+    /// Turns a registered relation into a constraint. Transactions
+    /// adding tuples to its output are rejected, as described on
+    /// `Server::check_constraints`.
+    RegisterConstraint(String),
+    /// Removes a named relation, unless it is still in use.
+    Unregister(String),
     /// Creates a named input handle that can be `Transact`ed upon.
     CreateAttribute(CreateAttribute),
+    /// Removes an attribute and releases its traces, unless it is
+    /// still in use.
+    DropAttribute(String),
     /// Advances the specified domain to the specified time.
     AdvanceDomain(Option<String>, Time),
     /// Requests a domain advance to whatever epoch the server
@@ -181,11 +233,15 @@ pub enum Request<A: AsAid + From<&'static str>> {
     CloseInput(String),
     /// Client has disconnected.
     Disconnect,
-    /// Requests any setup logic that needs to be executed
-    /// deterministically across all workers.
-    Setup,
+    /// Applies the given requests in order, identically on all
+    /// workers. Attributes, rules, and constraints that already exist
+    /// with the same definition are left alone, s.t. the same setup
+    /// can be applied repeatedly.
+    Setup(Vec<Request<A>>),
     /// Requests a heartbeat containing status information.
     Status,
+    /// Requests the names and configurations of all attributes.
+    Schema,
     /// Requests orderly shutdown of the system.
     Shutdown,
 }
@@ -212,6 +268,14 @@ where
     // user queries might be one-off and not result in a new domain
     // being created.
     shutdown_handles: HashMap<A, ShutdownHandle>,
+    // Mapping from subscription ids to the names of the queries they
+    // are interested in. Plain interests are tracked by query name.
+    subscriptions: HashMap<A, A>,
+    // Arranged results of queries, shared by all plain interests in
+    // them.
+    results: HashMap<A, TraceKeyHandle<Vec<Value>, T, isize>>,
+    // Shutdown handles of each client's view of shared results.
+    views: HashMap<A, HashMap<Token, ShutdownHandle>>,
     /// Probe keeping track of overall dataflow progress.
     pub probe: ProbeHandle<T>,
     /// Scheduler managing deferred operator activations.
@@ -220,6 +284,23 @@ where
     timely_events: Option<Rc<EventLink<Duration, (Duration, usize, TimelyEvent)>>>,
     // Link to replayable Differential logging events.
     differential_events: Option<Rc<EventLink<Duration, (Duration, usize, DifferentialEvent)>>>,
+    // Link to replayable Declarative logging events.
+    declarative_events: Option<Rc<EventLink<Duration, (Duration, usize, DeclarativeEvent)>>>,
+    // Datoms produced by attribute sinks, waiting to be transacted.
+    sunk_datoms: Rc<RefCell<Vec<Datom<A>>>>,
+    // Sources keep running for as long as this is around.
+    sources_alive: Option<Rc<()>>,
+    // Transactions whose clients want to know when they become
+    // visible, together with the time they are waiting for.
+    pending_reports: Vec<(Token, TxId, T)>,
+    // Mapping from constraint names to their shutdown handles.
+    constraints: HashMap<A, ShutdownHandle>,
+    // Tuples added to the output of any constraint, by time.
+    violations: Rc<RefCell<BTreeMap<T, Vec<(A, Vec<Value>)>>>>,
+    // Transactions waiting to be checked against all constraints,
+    // together with the time they are waiting for and the times
+    // their datoms were introduced at.
+    pending_checks: Vec<(Token, TxId, T, Vec<T>, Vec<Datom<A>>)>,
 }
 
 impl<A, T, Token> Server<A, T, Token>
@@ -239,6 +320,7 @@ where
     pub fn new_at(config: Configuration, t0: Instant) -> Self {
         let timely_events = Some(Rc::new(EventLink::new()));
         let differential_events = Some(Rc::new(EventLink::new()));
+        let declarative_events = Some(Rc::new(EventLink::new()));
 
         let probe = ProbeHandle::new();
 
@@ -248,36 +330,45 @@ where
             internal: Domain::new(Default::default()),
             interests: HashMap::new(),
             shutdown_handles: HashMap::new(),
+            subscriptions: HashMap::new(),
+            results: HashMap::new(),
+            views: HashMap::new(),
             scheduler: Rc::new(RefCell::new(Scheduler::from(probe.clone()))),
             probe,
             timely_events,
             differential_events,
+            declarative_events,
+            sunk_datoms: Rc::new(RefCell::new(Vec::new())),
+            sources_alive: Some(Rc::new(())),
+            pending_reports: Vec::new(),
+            constraints: HashMap::new(),
+            violations: Rc::new(RefCell::new(BTreeMap::new())),
+            pending_checks: Vec::new(),
         }
     }
 
-    /// Returns commands to install built-in plans.
+    /// Returns commands to install built-in plans, to be applied
+    /// ahead of any user-provided setup.
     pub fn builtins() -> Vec<Request<A>> {
-        vec![
-            // Request::CreateAttribute(CreateAttribute {
-            //     name: "df.pattern/e".to_string(),
-            //     semantics: InputSemantics::Raw,
-            // }),
-            // Request::CreateAttribute(CreateAttribute {
-            //     name: "df.pattern/a".to_string(),
-            //     semantics: InputSemantics::Raw,
-            // }),
-            // Request::CreateAttribute(CreateAttribute {
-            //     name: "df.pattern/v".to_string(),
-            //     semantics: InputSemantics::Raw,
-            // }),
-        ]
+        Vec::new()
     }
 
     /// Drops all shutdown handles associated with the specified
     /// query, resulting in its dataflow getting cleaned up.
     fn shutdown_query(&mut self, name: &A) {
         info!("Shutting down {}", name);
-        self.shutdown_handles.remove(name);
+
+        self.subscriptions.remove(name);
+        self.views.remove(name);
+        self.results.remove(name);
+
+        if self.shutdown_handles.remove(name).is_some() {
+            if let Some(logger) = self.internal.logger() {
+                logger.log(RuleShutdownEvent {
+                    name: name.to_string(),
+                });
+            }
+        }
     }
 
     /// Handles a Transact request.
@@ -295,12 +386,397 @@ where
         }
     }
 
+    /// Handles a TransactReport request. Returns the time at which
+    /// the transaction was inserted, which is also the time the
+    /// server's probe has to pass before results reflect it. The
+    /// client will be reported by `visible_transactions` once that
+    /// has happened.
+    pub fn transact_reported(
+        &mut self,
+        tx_data: Vec<Datom<A>>,
+        owner: usize,
+        worker_index: usize,
+        client: Token,
+        tx: TxId,
+    ) -> Result<T, Error> {
+        let time = self.visibility_time(&tx_data);
+
+        self.transact(tx_data, owner, worker_index)?;
+
+        if owner == worker_index {
+            self.pending_reports.push((client, tx, time.clone()));
+        }
+
+        Ok(time)
+    }
+
+    /// Returns the time at which the effects of the given inputs can
+    /// be observed, if they were transacted now.
+    fn visibility_time(&self, tx_data: &[Datom<A>]) -> T {
+        // Datoms at explicit times might become visible only later.
+        tx_data
+            .iter()
+            .filter_map(|Datom(_e, _a, _v, t, _diff)| t.clone())
+            .fold(self.internal.epoch().clone(), |time, t| time.join(&t.into()))
+    }
+
+    /// Handles a RegisterConstraint request. The named rule must have
+    /// been registered before. Any transaction adding tuples to its
+    /// output will be rejected.
+    pub fn register_constraint<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: A,
+        scope: &mut S,
+    ) -> Result<(), Error> {
+        if self.constraints.contains_key(&name) {
+            // Constraints are defined by their rule alone, so there
+            // is nothing to do.
+            return Ok(());
+        }
+
+        let (relation, shutdown_handle) = self.implement_relation(name.clone(), scope)?;
+
+        // Every worker might have to reject a transaction, so every
+        // worker must see all violations.
+        let violations = Rc::downgrade(&self.violations);
+        let constraint = name.clone();
+
+        let mut recvd: Vec<ResultDiff<T>> = Vec::new();
+        let mut vector = Vec::new();
+
+        relation
+            .inner
+            .broadcast()
+            .unary_frontier(Pipeline, "Constraint", move |_cap, _info| {
+                move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                    input.for_each(|_cap, data| {
+                        data.swap(&mut vector);
+                        recvd.extend(vector.drain(..));
+                    });
+
+                    let complete = drain_complete(&mut recvd, input.frontier());
+
+                    if let Some(violations) = violations.upgrade() {
+                        let mut violations = violations.borrow_mut();
+
+                        for (tuple, time, diff) in complete.into_iter() {
+                            if diff > 0 {
+                                violations
+                                    .entry(time)
+                                    .or_insert_with(Vec::new)
+                                    .push((constraint.clone(), tuple));
+                            }
+                        }
+                    }
+                }
+            })
+            .probe_with(&mut self.probe);
+
+        self.constraints.insert(name, shutdown_handle);
+
+        Ok(())
+    }
+
+    /// Remembers a transaction, s.t. it can be checked against all
+    /// constraints once its time has been closed. Must be called
+    /// before the transaction is handed to `transact`. Violations are
+    /// only known by time, so a transaction introducing datoms at a
+    /// time shared with another transaction that is still waiting to
+    /// be checked is refused. Such transactions must be separated by
+    /// advancing the domain.
+    pub fn watch_constraints(
+        &mut self,
+        tx_data: &[Datom<A>],
+        owner: usize,
+        worker_index: usize,
+        client: Token,
+        tx: TxId,
+    ) -> Result<(), Error> {
+        // Only the owner introduces inputs, so only the owner can
+        // retract them again.
+        if owner == worker_index && !self.constraints.is_empty() {
+            let epoch = self.internal.epoch().clone();
+            let mut times: Vec<T> = tx_data
+                .iter()
+                .map(|Datom(_e, _a, _v, t, _diff)| match t {
+                    None => epoch.clone(),
+                    Some(t) => t.clone().into(),
+                })
+                .collect();
+
+            times.sort();
+            times.dedup();
+
+            // Requests of a single transaction are checked together.
+            let shared = self.pending_checks.iter().find(
+                |(other_client, other_tx, _time, other_times, _tx_data)| {
+                    (*other_client != client || *other_tx != tx)
+                        && other_times.iter().any(|t| times.contains(t))
+                },
+            );
+
+            if let Some((_client, other_tx, _time, _times, _tx_data)) = shared {
+                return Err(Error::conflict(format!(
+                    "Transaction {} shares a time with transaction {}, which is still being checked against constraints.",
+                    tx, other_tx
+                )));
+            }
+
+            let time = self.visibility_time(tx_data);
+            self.pending_checks
+                .push((client, tx, time, times, tx_data.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /// Rejects all watched transactions that have caused any
+    /// constraint to produce new tuples at the time of any of their
+    /// datoms. Their inputs are retracted and an error is returned
+    /// for each of them.
+    ///
+    /// Rejections are compensating retractions at a later time, not
+    /// a rollback. In particular, retracting a rejected write to a
+    /// LastWriteWins attribute removes the value altogether, it does
+    /// not restore the value it replaced.
+    pub fn check_constraints(&mut self) -> Result<Vec<(Token, TxId, Error)>, Error> {
+        let probe = self.probe.clone();
+
+        let (ready, pending): (Vec<_>, Vec<_>) = self
+            .pending_checks
+            .drain(..)
+            .partition(|(_client, _tx, time, _times, _tx_data)| !probe.less_equal(time));
+
+        self.pending_checks = pending;
+
+        let mut rejected = Vec::new();
+        let mut retractions = Vec::new();
+
+        {
+            let violations = self.violations.borrow();
+
+            for (client, tx, _time, times, tx_data) in ready.into_iter() {
+                let tuples: Vec<&(A, Vec<Value>)> = times
+                    .iter()
+                    .filter_map(|time| violations.get(time))
+                    .flat_map(|tuples| tuples.iter())
+                    .collect();
+
+                if !tuples.is_empty() {
+                    retractions.extend(
+                        tx_data
+                            .into_iter()
+                            .map(|Datom(e, a, v, _t, diff)| Datom(e, a, v, None, -diff)),
+                    );
+
+                    let error = Error::conflict(format!(
+                        "Transaction {} violates constraints: {:?}",
+                        tx, tuples
+                    ));
+
+                    rejected.push((client, tx, error));
+                }
+            }
+        }
+
+        if !retractions.is_empty() {
+            self.internal.transact(retractions)?;
+        }
+
+        // Rejected transactions will never become visible.
+        self.pending_reports.retain(|(client, tx, _time)| {
+            !rejected
+                .iter()
+                .any(|(rejected_client, rejected_tx, _error)| {
+                    client == rejected_client && tx == rejected_tx
+                })
+        });
+
+        // Violations that no pending check can refer to anymore are
+        // discarded.
+        let pending_checks = &self.pending_checks;
+        let mut violations = self.violations.borrow_mut();
+        let outdated: Vec<T> = violations
+            .keys()
+            .filter(|time| !probe.less_equal(time))
+            .filter(|time| {
+                !pending_checks
+                    .iter()
+                    .any(|(_client, _tx, _time, times, _tx_data)| times.contains(time))
+            })
+            .cloned()
+            .collect();
+
+        for time in outdated.iter() {
+            violations.remove(time);
+        }
+
+        Ok(rejected)
+    }
+
+    /// Returns all reported transactions whose effects have become
+    /// visible in every dataflow since the last call.
+    pub fn visible_transactions(&mut self) -> Vec<(Token, TxId)> {
+        let probe = &self.probe;
+        let mut visible = Vec::new();
+
+        self.pending_reports.retain(|(client, tx, time)| {
+            if probe.less_equal(time) {
+                true
+            } else {
+                visible.push((*client, *tx));
+                false
+            }
+        });
+
+        visible
+    }
+
     /// Handles an Interest request.
     pub fn interest<S: Scope<Timestamp = T>>(
         &mut self,
         name: A,
         scope: &mut S,
     ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        self.subscription(name.clone(), name, None, scope)
+    }
+
+    /// Handles an Interest request tracked under the given
+    /// subscription id. A subscription resumed from some time only
+    /// produces results at later times. This requires the traces of
+    /// all attributes involved to still distinguish those times,
+    /// otherwise the client has to start over from a snapshot.
+    pub fn subscription<S: Scope<Timestamp = T>>(
+        &mut self,
+        id: A,
+        name: A,
+        resume_from: Option<T>,
+        scope: &mut S,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        if let Some(ref since) = resume_from {
+            let mut attributes = HashSet::new();
+
+            for rule in collect_dependencies(&self.internal, &[name.clone()])?.iter() {
+                attributes.extend(rule.plan.dependencies().attributes.into_iter());
+            }
+
+            if !self.internal.distinguishes(&attributes, since) {
+                return Err(Error::snapshot_required(format!(
+                    "History of {} at {:?} has been compacted.",
+                    name, since
+                )));
+            }
+        }
+
+        let (relation, shutdown_handle) = self.implement_relation(name.clone(), scope)?;
+
+        if id != name {
+            self.subscriptions.insert(id.clone(), name);
+        }
+
+        self.shutdown_handles.insert(id, shutdown_handle);
+
+        match resume_from {
+            None => Ok(relation),
+            Some(since) => Ok(relation
+                .inner
+                .filter(move |(_tuple, time, _diff)| !time.less_equal(&since))
+                .as_collection()),
+        }
+    }
+
+    /// Arranges the results of the named query, s.t. the views of
+    /// any number of interested clients can be attached to them. The
+    /// arrangement is tracked just like an interest.
+    pub fn share<S: Scope<Timestamp = T>>(&mut self, name: A, scope: &mut S) -> Result<(), Error> {
+        let arranged = self.interest(name.clone(), scope)?.arrange_by_self();
+
+        arranged.stream.probe_with(&mut self.probe);
+        self.results.insert(name, arranged.trace);
+
+        Ok(())
+    }
+
+    /// Attaches a client's view to the shared results of the named
+    /// query. Views start out with a snapshot as of the time the
+    /// results have been compacted to.
+    pub fn view<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: A,
+        client: Token,
+        scope: &mut S,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        let (arranged, shutdown) = match self.results.get_mut(&name) {
+            None => {
+                return Err(Error::not_found(format!(
+                    "Results of {} are not shared.",
+                    name
+                )));
+            }
+            Some(trace) => trace.import_frontier(scope, &name.to_string()),
+        };
+
+        self.views
+            .entry(name)
+            .or_insert_with(HashMap::new)
+            .insert(client, ShutdownHandle::from_button(shutdown));
+
+        Ok(arranged.as_collection(|tuple, _unit| tuple.clone()))
+    }
+
+    /// Allows the shared results of all queries to compact up to the
+    /// current epoch.
+    pub fn advance_results(&mut self) {
+        let frontier = [self.internal.epoch().clone()];
+
+        for trace in self.results.values_mut() {
+            trace.advance_by(&frontier);
+            trace.distinguish_since(&frontier);
+        }
+    }
+
+    /// Handles a Subscribe request. The subscription is tracked just
+    /// like an interest, s.t. its trace import is released once no
+    /// client is subscribed anymore.
+    pub fn subscribe<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: A,
+        scope: &mut S,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        let (propose, shutdown) = match self.internal.forward_propose(&name) {
+            None => {
+                return Err(Error::not_found(format!(
+                    "Attribute {} does not exist.",
+                    name
+                )));
+            }
+            Some(propose_trace) => propose_trace.import_frontier(scope, &name.to_string()),
+        };
+
+        self.shutdown_handles
+            .insert(name, ShutdownHandle::from_button(shutdown));
+
+        Ok(propose.as_collection(|e, v| vec![e.clone(), v.clone()]))
+    }
+
+    /// Handles a Query request. Unlike interests, one-shot queries
+    /// are not tracked by the server. Instead the caller is handed
+    /// the shutdown handle and is responsible for dropping it, once
+    /// the snapshot has been taken.
+    pub fn query<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: A,
+        scope: &mut S,
+    ) -> Result<(Collection<S, Vec<Value>, isize>, ShutdownHandle), Error> {
+        self.implement_relation(name, scope)
+    }
+
+    /// Implements the named relation in the provided scope.
+    fn implement_relation<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: A,
+        scope: &mut S,
+    ) -> Result<(Collection<S, Vec<Value>, isize>, ShutdownHandle), Error> {
         let (mut rel_map, shutdown_handle) = if self.config.enable_optimizer {
             implement_neu(scope, &mut self.internal, name.clone())?
         } else {
@@ -313,30 +789,141 @@ where
                 name
             ))),
             Some(relation) => {
-                self.shutdown_handles.insert(name, shutdown_handle);
+                if let Some(logger) = self.internal.logger() {
+                    logger.log(RuleImplementedEvent {
+                        name: name.to_string(),
+                    });
+                }
 
-                Ok(relation)
+                Ok((relation, shutdown_handle))
             }
         }
     }
 
-    /// Handles a Register request.
-    pub fn register(&mut self, req: Register<A>) -> Result<(), Error> {
-        let Register { rules, .. } = req;
+    /// Handles a Register request. Re-registering an identical rule
+    /// is a no-op, whereas redefining a rule is only allowed if the
+    /// request explicitly asks for it. Dataflows depending on a
+    /// replaced rule are shut down, and their names returned together
+    /// with the clients that were interested in them. Constraints
+    /// depending on a replaced rule are dropped and must be
+    /// registered again.
+    pub fn register(&mut self, req: Register<A>) -> Result<Vec<(A, HashSet<Token>)>, Error> {
+        let Register { rules, replace, .. } = req;
+        let replace = replace.unwrap_or(false);
+
+        let mut replaced = HashSet::new();
+
+        for rule in rules.iter() {
+            if let Some(existing) = self.internal.rule(&rule.name) {
+                if existing.plan != rule.plan {
+                    if replace {
+                        replaced.insert(rule.name.clone());
+                    } else {
+                        return Err(Error::conflict(format!(
+                            "Rule {} is already defined differently.",
+                            rule.name
+                        )));
+                    }
+                }
+            }
+        }
+
+        // Dependencies must be resolved against the old definitions.
+        let stale: Vec<A> = self
+            .shutdown_handles
+            .keys()
+            .filter(|key| {
+                let name = self.subscriptions.get(*key).unwrap_or(*key);
+
+                match collect_dependencies(&self.internal, &[name.clone()]) {
+                    Err(_) => false,
+                    Ok(dependencies) => dependencies
+                        .iter()
+                        .any(|dependency| replaced.contains(&dependency.name)),
+                }
+            })
+            .cloned()
+            .collect();
+
+        // Constraints are enforced by their own dataflows, which
+        // would otherwise keep enforcing the old definitions.
+        let stale_constraints: Vec<A> = self
+            .constraints
+            .keys()
+            .filter(|name| match collect_dependencies(&self.internal, &[(*name).clone()]) {
+                Err(_) => false,
+                Ok(dependencies) => dependencies
+                    .iter()
+                    .any(|dependency| replaced.contains(&dependency.name)),
+            })
+            .cloned()
+            .collect();
 
         for rule in rules.into_iter() {
-            if self.internal.rules.contains_key(&rule.name) {
-                // @TODO panic if hashes don't match
-                // panic!("Attempted to re-register a named relation");
-                continue;
-            } else {
+            if !self.internal.rules.contains_key(&rule.name) || replaced.contains(&rule.name) {
                 self.internal.rules.insert(rule.name.clone(), rule);
             }
         }
 
+        for name in stale_constraints.into_iter() {
+            warn!("constraint {} depends on a redefined rule and will be dropped", name);
+            self.constraints.remove(&name);
+        }
+
+        let mut evicted = Vec::with_capacity(stale.len());
+        for name in stale.into_iter() {
+            warn!("{} depends on a redefined rule and will be shut down", name);
+
+            self.shutdown_query(&name);
+            let clients = self.interests.remove(&name).unwrap_or_default();
+
+            evicted.push((name, clients));
+        }
+
+        Ok(evicted)
+    }
+
+    /// Handles an Unregister request.
+    pub fn unregister(&mut self, name: A) -> Result<(), Error> {
+        // Subscriptions with an id are known by their id, but depend
+        // on the rule all the same.
+        let is_interesting = self.shutdown_handles.contains_key(&name)
+            || self.constraints.contains_key(&name)
+            || self.subscriptions.values().any(|subscribed| subscribed == &name)
+            || self
+                .interests
+                .get(&name)
+                .map_or(false, |clients| !clients.is_empty());
+
+        if is_interesting {
+            return Err(Error::conflict(format!(
+                "Rule {} still has interested clients.",
+                name
+            )));
+        }
+
+        self.internal.unregister(&name)?;
+
         Ok(())
     }
 
+    /// Handles a DropAttribute request.
+    pub fn drop_attribute(&mut self, name: A) -> Result<(), Error> {
+        let is_subscribed = self
+            .interests
+            .get(&name)
+            .map_or(false, |clients| !clients.is_empty());
+
+        if is_subscribed {
+            return Err(Error::conflict(format!(
+                "Attribute {} still has subscribed clients.",
+                name
+            )));
+        }
+
+        self.internal.drop_attribute(&name)
+    }
+
     /// Handles a CreateAttribute request.
     pub fn create_attribute<X, S>(
         &mut self,
@@ -349,6 +936,19 @@ where
         S: Scope<Timestamp = T>,
         S::Timestamp: std::convert::Into<crate::timestamp::Time>,
     {
+        let name = name.into();
+
+        if let Some(existing) = self.internal.attributes.get(&name) {
+            if *existing == config {
+                return Ok(());
+            } else {
+                return Err(Error::conflict(format!(
+                    "Attribute {} already exists with a different configuration.",
+                    name
+                )));
+            }
+        }
+
         let ((handle, cap), pairs) =
             scope.new_unordered_input::<((Value, Value), S::Timestamp, isize)>();
 
@@ -360,7 +960,7 @@ where
             InputSemantics::Distinct => pairs.as_collection().distinct(),
         };
 
-        let mut scoped_domain = ((handle, cap), tuples).as_singleton_domain(name.into());
+        let mut scoped_domain = ((handle, cap), tuples).as_singleton_domain(name);
 
         if let Some(slack) = config.trace_slack {
             scoped_domain = scoped_domain.with_slack(slack.into());
@@ -381,18 +981,151 @@ where
         Ok(())
     }
 
+    /// Handles an attribute sink, routing the `(e, v)` pairs found at
+    /// the specified offsets of each result tuple into the named
+    /// attribute. Results are fed through the attribute's input
+    /// session, s.t. its input semantics and indices apply to them
+    /// just as they would to transacted data. Attributes that do not
+    /// exist yet are created with full support for Hector.
+    ///
+    /// Results are only buffered here, they must be moved into the
+    /// domain via `transact_sunk`.
+    pub fn sink_attribute<S, P>(
+        &mut self,
+        scope: &mut S,
+        stream: &Stream<S, ResultDiff<T>>,
+        pact: P,
+        name: A,
+        e_offset: usize,
+        v_offset: usize,
+    ) -> Result<(), Error>
+    where
+        S: Scope<Timestamp = T>,
+        S::Timestamp: std::convert::Into<crate::timestamp::Time>,
+        P: ParallelizationContract<T, ResultDiff<T>>,
+    {
+        if !self.internal.has_attribute(&name) {
+            let config = AttributeConfig {
+                input_semantics: InputSemantics::Raw,
+                trace_slack: None,
+                index_direction: IndexDirection::Both,
+                query_support: QuerySupport::AdaptiveWCO,
+            };
+
+            self.create_attribute(scope, name.clone(), config)?;
+        } else if !self.internal.has_input(&name) {
+            return Err(Error::conflict(format!(
+                "Attribute {} is sourced and can't be sunk into.",
+                name
+            )));
+        }
+
+        let sunk_datoms = Rc::downgrade(&self.sunk_datoms);
+
+        let mut recvd: Vec<ResultDiff<T>> = Vec::new();
+        let mut vector = Vec::new();
+
+        stream
+            .unary_frontier(pact, "AttributeSink", move |_cap, _info| {
+                move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                    input.for_each(|_cap, data| {
+                        data.swap(&mut vector);
+                        recvd.extend(vector.drain(..));
+                    });
+
+                    // Only consolidated results are forwarded, s.t.
+                    // retractions cancel out before they reach the
+                    // attribute.
+                    let complete = drain_complete(&mut recvd, input.frontier());
+
+                    if let Some(sunk_datoms) = sunk_datoms.upgrade() {
+                        let mut sunk_datoms = sunk_datoms.borrow_mut();
+                        for (mut tuple, _time, diff) in complete.into_iter() {
+                            // Offsets are supplied by clients, so we
+                            // can't trust them to fit every tuple.
+                            if e_offset >= tuple.len() || v_offset >= tuple.len() {
+                                error!(
+                                    "skipping result {:?} sunk into {}, offsets {} / {} are out of bounds",
+                                    tuple, name, e_offset, v_offset
+                                );
+                                continue;
+                            }
+
+                            let v = tuple[v_offset].clone();
+                            let e = tuple.swap_remove(e_offset);
+
+                            sunk_datoms.push(Datom(e, name.clone(), v, None, diff));
+                        }
+                    }
+                }
+            })
+            .probe_with(&mut self.probe);
+
+        Ok(())
+    }
+
+    /// Transacts all datoms produced by attribute sinks since the
+    /// last call. Datoms sunk into attributes that no longer accept
+    /// input are dropped and reported, without affecting those sunk
+    /// into other attributes.
+    pub fn transact_sunk(&mut self) -> Result<(), Error> {
+        let mut missing = HashSet::new();
+        let tx_data: Vec<Datom<A>> = {
+            let internal = &self.internal;
+            self.sunk_datoms
+                .borrow_mut()
+                .drain(..)
+                .filter(|datom| {
+                    if internal.has_input(&datom.1) {
+                        true
+                    } else {
+                        missing.insert(datom.1.clone());
+                        false
+                    }
+                })
+                .collect()
+        };
+
+        if !tx_data.is_empty() {
+            self.internal.transact(tx_data)?;
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::not_found(format!(
+                "Sunk attributes {:?} no longer accept input.",
+                missing
+            )))
+        }
+    }
+
     /// Returns a fresh sourcing context, useful for installing 3DF
     /// compatible sources manually.
     pub fn make_sourcing_context(&self) -> SourcingContext<T> {
         SourcingContext {
             t0: self.t0,
             scheduler: Rc::downgrade(&self.scheduler),
+            alive: self
+                .sources_alive
+                .as_ref()
+                .map(Rc::downgrade)
+                .unwrap_or_else(Weak::new),
             domain_probe: self.internal.domain_probe().clone(),
             timely_events: self.timely_events.clone().unwrap(),
             differential_events: self.differential_events.clone().unwrap(),
+            declarative_events: self.declarative_events.clone().unwrap(),
         }
     }
 
+    /// Asks all sources to stop and release their capabilities,
+    /// s.t. dataflows depending on them can complete. Sources only
+    /// notice once they are scheduled next. Logging sources stop
+    /// once logging is shut down instead.
+    pub fn close_sources(&mut self) {
+        self.sources_alive = None;
+    }
+
     /// Handles a RegisterSource request.
     pub fn register_source<S>(
         &mut self,
@@ -478,6 +1211,10 @@ where
         if let Some(entry) = self.interests.get_mut(name) {
             entry.remove(&client);
 
+            if let Some(views) = self.views.get_mut(name) {
+                views.remove(&client);
+            }
+
             if entry.is_empty() {
                 self.shutdown_query(name);
                 self.interests.remove(name);
@@ -498,6 +1235,73 @@ where
         Ok(())
     }
 
+    /// Returns all attributes and their configurations, ordered by
+    /// name.
+    pub fn schema(&self) -> Vec<(A, AttributeConfig)> {
+        let mut schema: Vec<(A, AttributeConfig)> = self
+            .internal
+            .attributes
+            .iter()
+            .map(|(name, config)| (name.clone(), config.clone()))
+            .collect();
+
+        schema.sort();
+        schema
+    }
+
+    /// Reports this worker's view of the server state, for diagnosing
+    /// stalls.
+    #[cfg(feature = "serde_json")]
+    pub fn status(&mut self) -> serde_json::Value {
+        let mut attributes = serde_json::Map::new();
+        let mut names: Vec<A> = self.internal.attributes.keys().cloned().collect();
+        names.sort();
+
+        for name in names.iter() {
+            let config = self.internal.attributes[name].clone();
+
+            attributes.insert(
+                name.to_string(),
+                serde_json::json!({
+                    "config": config,
+                    "trace_size": self.internal.trace_size(name),
+                    "transactable": self.internal.has_input(name),
+                }),
+            );
+        }
+
+        let mut rules: Vec<String> = self.internal.rules.keys().map(|name| name.to_string()).collect();
+        rules.sort();
+
+        let mut interests = serde_json::Map::new();
+        let mut clients = HashSet::new();
+        for (name, tokens) in self.interests.iter() {
+            interests.insert(name.to_string(), serde_json::json!(tokens.len()));
+            clients.extend(tokens.iter().cloned());
+        }
+
+        let mut constraints: Vec<String> = self.constraints.keys().map(|name| name.to_string()).collect();
+        constraints.sort();
+
+        let frontier: Vec<String> = self
+            .probe
+            .with_frontier(|frontier| frontier.iter().map(|t| format!("{:?}", t)).collect());
+
+        serde_json::json!({
+            "epoch": format!("{:?}", self.internal.epoch()),
+            "frontier": frontier,
+            "attributes": attributes,
+            "rules": rules,
+            "constraints": constraints,
+            "interests": interests,
+            "interested_clients": clients.len(),
+            "dataflows": self.shutdown_handles.len(),
+            "pending_reports": self.pending_reports.len(),
+            "pending_checks": self.pending_checks.len(),
+            "scheduled": self.scheduler.borrow().realtime.len(),
+        })
+    }
+
     /// Returns true iff the probe is behind any input handle. Mostly
     /// used as a convenience method during testing. Using this within
     /// `step_while` is not safe in general and might lead to stalls.
@@ -519,6 +1323,7 @@ where
         self.register(Register {
             rules: vec![rule],
             publish: vec![publish_name],
+            replace: None,
         })
         .unwrap();
 
@@ -535,7 +1340,7 @@ where
     Token: Hash + Eq + Copy,
 {
     /// Registers loggers for use in the various logging sources.
-    pub fn enable_logging<Al: Allocate>(&self, worker: &mut Worker<Al>) -> Result<(), Error> {
+    pub fn enable_logging<Al: Allocate>(&mut self, worker: &mut Worker<Al>) -> Result<(), Error> {
         let mut timely_logger = BatchLogger::new(self.timely_events.clone().unwrap());
         worker
             .log_register()
@@ -550,11 +1355,27 @@ where
                 differential_logger.publish_batch(time, data)
             });
 
+        let mut declarative_logger = BatchLogger::new(self.declarative_events.clone().unwrap());
+        worker
+            .log_register()
+            .insert::<DeclarativeEvent, _>("declarative", move |time, data| {
+                declarative_logger.publish_batch(time, data)
+            });
+
+        // The domain logs transactions and trace sizes, so it needs
+        // a handle of its own.
+        let logger = worker.log_register().get::<DeclarativeEvent>("declarative");
+        self.internal.set_logger(logger);
+
         Ok(())
     }
 
     /// Unregisters loggers.
-    pub fn shutdown_logging<Al: Allocate>(&self, worker: &mut Worker<Al>) -> Result<(), Error> {
+    pub fn shutdown_logging<Al: Allocate>(&mut self, worker: &mut Worker<Al>) -> Result<(), Error> {
+        // Dropping the domain's handle is required for the
+        // declarative logging stream to close.
+        self.internal.set_logger(None);
+
         worker
             .log_register()
             .insert::<TimelyEvent, _>("timely", move |_time, _data| {});
@@ -1,86 +1,179 @@
 //! Operator and utilities to write output diffs into csv files.
 
+use std::collections::BTreeMap;
+
 use timely::dataflow::channels::pact::ParallelizationContract;
-use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
-use timely::dataflow::operators::generic::FrontieredInputHandle;
-use timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::generic::{Operator, OutputHandle};
+use timely::dataflow::operators::probe::Probe;
+use timely::dataflow::{ProbeHandle, Scope, Stream};
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use crate::{Error, Output, ResultDiff, Time, Value};
 
-use super::Sinkable;
-use crate::{Error, ResultDiff};
+use super::{drain_complete, PlainTime, Sinkable, SinkingContext};
 
 /// A local filesystem data sink.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct CsvFile {
-    /// Path to a file on each workers local filesystem.
+    /// Path to a file on each workers local filesystem. Every
+    /// worker writes to its own file, suffixed with its index.
     pub path: String,
-    /// Does the file include a header?
-    pub has_headers: bool,
+    /// Optional column names for the tuple values. If provided, a
+    /// header row is written before any results.
+    pub header: Option<Vec<String>>,
     /// Column delimiter to use.
     pub delimiter: u8,
     /// Allow flexible length records?
     pub flexible: bool,
+    /// Write the full, consolidated result at each closed timestamp,
+    /// rather than just the differences.
+    pub snapshots: bool,
 }
 
-impl Sinkable<u64> for CsvFile {
+impl<T> Sinkable<T> for CsvFile
+where
+    T: Timestamp + Lattice + std::convert::Into<Time>,
+{
     fn sink<S, P>(
         &self,
-        stream: &Stream<S, ResultDiff<u64>>,
+        stream: &Stream<S, ResultDiff<T>>,
         pact: P,
-    ) -> Result<Stream<S, ResultDiff<u64>>, Error>
+        probe: &mut ProbeHandle<T>,
+        _context: SinkingContext,
+    ) -> Result<Option<Stream<S, Output>>, Error>
     where
-        S: Scope<Timestamp = u64>,
-        P: ParallelizationContract<S::Timestamp, ResultDiff<u64>>,
+        S: Scope<Timestamp = T>,
+        P: ParallelizationContract<S::Timestamp, ResultDiff<T>>,
     {
+        let path = format!("{}.{}", self.path, stream.scope().index());
+
         let writer_result = csv::WriterBuilder::new()
-            .has_headers(self.has_headers)
+            .has_headers(false)
             .delimiter(self.delimiter)
-            .from_path(&self.path);
-
-        match writer_result {
-            Err(error) => Err(Error::fault(format!("Failed to create writer: {}", error))),
-            Ok(mut writer) => {
-                let mut recvd = Vec::new();
-                let mut vector = Vec::new();
-
-                let name = format!("CsvFile({})", &self.path);
-
-                let mut builder = OperatorBuilder::new(name, stream.scope());
-                let mut input = builder.new_input(stream, pact);
-                let (_output, sunk) = builder.new_output();
-
-                builder.build(|_capabilities| {
-                    move |frontiers| {
-                        let mut input_handle =
-                            FrontieredInputHandle::new(&mut input, &frontiers[0]);
-
-                        input_handle.for_each(|_cap, data| {
-                            data.swap(&mut vector);
-                            // @TODO what to do with diff here?
-                            for (tuple, time, _diff) in vector.drain(..) {
-                                recvd.push((time, tuple));
-                            }
-                        });
+            .flexible(self.flexible)
+            .from_path(&path);
 
-                        recvd.sort_by(|x, y| x.0.cmp(&y.0));
+        let mut writer = match writer_result {
+            Err(error) => {
+                return Err(Error::fault(format!("Failed to create writer: {}", error)));
+            }
+            Ok(writer) => writer,
+        };
 
-                        // determine how many (which) elements to read from `recvd`.
-                        let count = recvd
-                            .iter()
-                            .filter(|&(ref time, _)| !input_handle.frontier().less_equal(time))
-                            .count();
+        if let Some(ref columns) = self.header {
+            let mut record = columns.clone();
+            record.push("time".to_string());
+            record.push("diff".to_string());
 
-                        for (_, tuple) in recvd.drain(..count) {
-                            writer.serialize(tuple).expect("failed to write record");
-                        }
+            if let Err(error) = writer.write_record(&record) {
+                return Err(Error::fault(format!("Failed to write header: {}", error)));
+            }
+        }
+
+        let snapshots = self.snapshots;
+        let mut state: BTreeMap<Vec<Value>, isize> = BTreeMap::new();
+
+        let mut recvd: Vec<ResultDiff<T>> = Vec::new();
+        let mut vector = Vec::new();
+
+        let name = format!("CsvFile({})", &path);
+
+        stream
+            .unary_frontier(pact, &name, move |_cap, _info| {
+                move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                    input.for_each(|_cap, data| {
+                        data.swap(&mut vector);
+                        recvd.extend(vector.drain(..));
+                    });
+
+                    let mut consolidated = drain_complete(&mut recvd, input.frontier());
+
+                    if consolidated.is_empty() {
+                        return;
+                    }
+
+                    let mut records = Vec::new();
+
+                    if snapshots {
+                        let mut idx = 0;
+                        while idx < consolidated.len() {
+                            let time = consolidated[idx].1.clone();
+
+                            while idx < consolidated.len() && consolidated[idx].1 == time {
+                                let (ref tuple, _, diff) = consolidated[idx];
+                                let count = {
+                                    let count = state.entry(tuple.clone()).or_insert(0);
+                                    *count += diff;
+                                    *count
+                                };
 
-                        if input_handle.frontier.is_empty() {
-                            println!("Inputs to csv sink have ceased.");
+                                if count == 0 {
+                                    state.remove(tuple);
+                                }
+
+                                idx += 1;
+                            }
+
+                            let time_field = PlainTime::from(time.into()).to_string();
+                            for (tuple, count) in state.iter() {
+                                records.push(to_record(tuple, &time_field, *count));
+                            }
+                        }
+                    } else {
+                        for (tuple, time, diff) in consolidated.drain(..) {
+                            let time_field = PlainTime::from(time.into()).to_string();
+                            records.push(to_record(&tuple, &time_field, diff));
                         }
                     }
-                });
 
-                Ok(sunk)
-            }
-        }
+                    // I/O failures must not take down the worker, so
+                    // we report them and drop the affected records.
+                    let written = records
+                        .iter()
+                        .map(|record| writer.write_record(record))
+                        .collect::<Result<(), _>>()
+                        .map_err(|error| error.to_string())
+                        .and_then(|_| writer.flush().map_err(|error| error.to_string()));
+
+                    if let Err(error) = written {
+                        error!("Failed to write to {}: {}", &path, error);
+                    }
+
+                    if input.frontier().is_empty() {
+                        info!("Inputs to csv sink have ceased.");
+                    }
+                }
+            })
+            .probe_with(probe);
+
+        Ok(None)
+    }
+}
+
+/// Turns a result tuple into a csv record, with time and diff
+/// appended as the last two columns.
+fn to_record(tuple: &[Value], time: &str, diff: isize) -> Vec<String> {
+    let mut record: Vec<String> = tuple.iter().map(format_value).collect();
+    record.push(time.to_string());
+    record.push(diff.to_string());
+
+    record
+}
+
+/// Renders a single value as a csv field.
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Aid(v) => v.clone(),
+        Value::String(v) => v.clone(),
+        Value::Bool(v) => v.to_string(),
+        Value::Number(v) => v.to_string(),
+        Value::Rational32(v) => v.to_string(),
+        Value::Eid(v) => v.to_string(),
+        Value::Instant(v) => v.to_string(),
+        Value::Uuid(v) => v.to_string(),
+        #[cfg(feature = "real")]
+        Value::Real(v) => v.to_string(),
     }
 }
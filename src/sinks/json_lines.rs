@@ -0,0 +1,174 @@
+//! Operator and utilities to write output diffs into files of
+//! newline-delimited json objects.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use timely::dataflow::channels::pact::ParallelizationContract;
+use timely::dataflow::operators::generic::{Operator, OutputHandle};
+use timely::dataflow::operators::probe::Probe;
+use timely::dataflow::{ProbeHandle, Scope, Stream};
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use serde_json::map::Map;
+use serde_json::Value as JValue;
+
+use crate::{Error, Output, ResultDiff, Time};
+
+use super::{drain_complete, PlainTime, Sinkable, SinkingContext};
+
+/// A local filesystem sink, writing one json object per result diff.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct JsonLines {
+    /// Path on each workers local filesystem. Every worker writes
+    /// to its own files, suffixed with its index and a running
+    /// segment index.
+    pub path: String,
+    /// Keys under which to write the values of the query's
+    /// variables, in order. Values without a name are keyed by their
+    /// offset. The `time` and `diff` keys are reserved.
+    pub columns: Vec<String>,
+    /// Start a new file once the current one has grown beyond this
+    /// many bytes.
+    pub max_bytes: Option<u64>,
+    /// Start a new file once the current one has been written to for
+    /// this long. This is checked whenever the sink is scheduled,
+    /// i.e. on new results or progress.
+    pub max_age: Option<Duration>,
+}
+
+/// The file currently being written to.
+struct Segment {
+    index: usize,
+    writer: BufWriter<File>,
+    bytes: u64,
+    opened_at: Instant,
+}
+
+impl Segment {
+    fn open(path: &str, index: usize) -> Result<Self, Error> {
+        match File::create(format!("{}.{}", path, index)) {
+            Err(error) => Err(Error::fault(format!("Failed to create file: {}", error))),
+            Ok(file) => Ok(Segment {
+                index,
+                writer: BufWriter::new(file),
+                bytes: 0,
+                opened_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl<T> Sinkable<T> for JsonLines
+where
+    T: Timestamp + Lattice + std::convert::Into<Time>,
+{
+    fn sink<S, P>(
+        &self,
+        stream: &Stream<S, ResultDiff<T>>,
+        pact: P,
+        probe: &mut ProbeHandle<T>,
+        _context: SinkingContext,
+    ) -> Result<Option<Stream<S, Output>>, Error>
+    where
+        S: Scope<Timestamp = T>,
+        P: ParallelizationContract<S::Timestamp, ResultDiff<T>>,
+    {
+        if let Some(column) = self
+            .columns
+            .iter()
+            .find(|column| *column == "time" || *column == "diff")
+        {
+            return Err(Error::incorrect(format!("Column name {} is reserved.", column)));
+        }
+
+        let path = format!("{}.{}", self.path, stream.scope().index());
+        let columns = self.columns.clone();
+        let max_bytes = self.max_bytes;
+        let max_age = self.max_age;
+
+        let mut segment = Segment::open(&path, 0)?;
+
+        let mut recvd: Vec<ResultDiff<T>> = Vec::new();
+        let mut vector = Vec::new();
+
+        let name = format!("JsonLines({})", &path);
+
+        stream
+            .unary_frontier(pact, &name, move |_cap, _info| {
+                move |input, _output: &mut OutputHandle<_, ResultDiff<T>, _>| {
+                    input.for_each(|_cap, data| {
+                        data.swap(&mut vector);
+                        recvd.extend(vector.drain(..));
+                    });
+
+                    // Segments are only ever rotated in between
+                    // timestamps, s.t. no timestamp is split across
+                    // files. Segments nothing has been written to yet
+                    // are never rotated.
+                    let is_full = max_bytes.map_or(false, |max| segment.bytes >= max);
+                    let is_old = max_age.map_or(false, |max| segment.opened_at.elapsed() >= max);
+
+                    if segment.bytes > 0 && (is_full || is_old) {
+                        if let Err(error) = segment.writer.flush() {
+                            error!("Failed to flush {}.{}: {}", &path, segment.index, error);
+                        }
+
+                        match Segment::open(&path, segment.index + 1) {
+                            Err(error) => error!("Failed to rotate {}: {}", &path, error.message),
+                            Ok(next) => segment = next,
+                        }
+                    }
+
+                    let complete = drain_complete(&mut recvd, input.frontier());
+
+                    if complete.is_empty() {
+                        return;
+                    }
+
+                    for (tuple, time, diff) in complete.into_iter() {
+                        let mut object = Map::new();
+
+                        for (idx, value) in tuple.into_iter().enumerate() {
+                            let key = match columns.get(idx) {
+                                None => idx.to_string(),
+                                Some(column) => column.clone(),
+                            };
+
+                            object.insert(key, JValue::from(value));
+                        }
+
+                        let time = match PlainTime::from(time.into()) {
+                            PlainTime::Number(t) => JValue::from(t),
+                            pair => JValue::from(pair.to_string()),
+                        };
+
+                        object.insert("time".to_string(), time);
+                        object.insert("diff".to_string(), JValue::from(diff as i64));
+
+                        let line = JValue::Object(object).to_string();
+
+                        // I/O failures must not take down the worker,
+                        // so we report them and drop the affected
+                        // results.
+                        if let Err(error) = writeln!(segment.writer, "{}", line) {
+                            error!("Failed to write to {}.{}: {}", &path, segment.index, error);
+                            break;
+                        }
+
+                        segment.bytes += line.len() as u64 + 1;
+                    }
+
+                    if let Err(error) = segment.writer.flush() {
+                        error!("Failed to flush {}.{}: {}", &path, segment.index, error);
+                    }
+                }
+            })
+            .probe_with(probe);
+
+        Ok(None)
+    }
+}
@@ -8,22 +8,57 @@ use timely::dataflow::channels::pact::ParallelizationContract;
 use timely::dataflow::operators::generic::{Operator, OutputHandle};
 use timely::dataflow::operators::probe::Probe;
 use timely::dataflow::{ProbeHandle, Scope, Stream};
+use timely::progress::frontier::MutableAntichain;
 use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
 
 use crate::{Error, Output, ResultDiff, Time};
 
-// #[cfg(feature = "csv-source")]
-// pub mod csv_file;
-// #[cfg(feature = "csv-source")]
-// pub use self::csv_file::CsvFile;
+#[cfg(feature = "csv-source")]
+pub mod csv_file;
+#[cfg(feature = "csv-source")]
+pub use self::csv_file::CsvFile;
 
 #[cfg(feature = "serde_json")]
 pub mod assoc_in;
 #[cfg(feature = "serde_json")]
 pub use self::assoc_in::AssocIn;
 
+#[cfg(feature = "serde_json")]
+pub mod json_lines;
+#[cfg(feature = "serde_json")]
+pub use self::json_lines::JsonLines;
+
+/// Timestamps as written by file sinks. Transaction ids and real
+/// times (in milliseconds) are plain numbers, bitemporal times are
+/// written as `system:event`.
+pub enum PlainTime {
+    /// A single number.
+    Number(u64),
+    /// A pair of system and event time.
+    Pair(u64, u64),
+}
+
+impl std::convert::From<Time> for PlainTime {
+    fn from(time: Time) -> Self {
+        match time {
+            Time::TxId(t) => PlainTime::Number(t),
+            Time::Real(t) => PlainTime::Number(t.as_millis() as u64),
+            Time::Bi(sys, event) => PlainTime::Pair(sys.as_millis() as u64, event),
+        }
+    }
+}
+
+impl std::fmt::Display for PlainTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PlainTime::Number(t) => write!(f, "{}", t),
+            PlainTime::Pair(sys, event) => write!(f, "{}:{}", sys, event),
+        }
+    }
+}
+
 /// A struct encapsulating any state required to create sinks.
 pub struct SinkingContext {
     /// The name of the dataflow feeding this sink.
@@ -56,12 +91,25 @@ where
 pub enum Sink {
     /// /dev/null, used for benchmarking
     TheVoid(Option<String>),
-    // /// CSV files
-    // #[cfg(feature = "csv-source")]
-    // CsvFile(CsvFile),
+    /// CSV files
+    #[cfg(feature = "csv-source")]
+    CsvFile(CsvFile),
     /// Nested Hash-Maps
     #[cfg(feature = "serde_json")]
     AssocIn(AssocIn),
+    /// Newline-delimited JSON files
+    #[cfg(feature = "serde_json")]
+    JsonLines(JsonLines),
+    /// An attribute of the server's domain. Requires a server, see
+    /// `Server::sink_attribute`.
+    Attribute {
+        /// The attribute to feed results into.
+        name: String,
+        /// Offset of the entity in each result tuple.
+        e_offset: usize,
+        /// Offset of the value in each result tuple.
+        v_offset: usize,
+    },
 }
 
 impl<T> Sinkable<T> for Sink
@@ -126,9 +174,49 @@ where
 
                 Ok(None)
             }
+            #[cfg(feature = "csv-source")]
+            Sink::CsvFile(ref sink) => sink.sink(stream, pact, probe, context),
             #[cfg(feature = "serde_json")]
             Sink::AssocIn(ref sink) => sink.sink(stream, pact, probe, context),
-            _ => unimplemented!(),
+            #[cfg(feature = "serde_json")]
+            Sink::JsonLines(ref sink) => sink.sink(stream, pact, probe, context),
+            Sink::Attribute { .. } => Err(Error::unsupported(
+                "Attribute sinks can only be installed by a server.",
+            )),
         }
     }
 }
+
+/// Removes all updates at times that are no longer in advance of the
+/// frontier from `pending` and returns them, sorted by time and with
+/// diffs consolidated within each time. Updates that cancel out are
+/// dropped.
+pub fn drain_complete<T: Timestamp>(
+    pending: &mut Vec<ResultDiff<T>>,
+    frontier: &MutableAntichain<T>,
+) -> Vec<ResultDiff<T>> {
+    let (mut complete, rest): (Vec<_>, Vec<_>) = pending
+        .drain(..)
+        .partition(|(_tuple, time, _diff)| !frontier.less_equal(time));
+
+    *pending = rest;
+
+    complete.sort_by(|x, y| (&x.1, &x.0).cmp(&(&y.1, &y.0)));
+
+    let mut consolidated: Vec<ResultDiff<T>> = Vec::with_capacity(complete.len());
+    for (tuple, time, diff) in complete.drain(..) {
+        let is_duplicate = match consolidated.last() {
+            None => false,
+            Some(last) => last.0 == tuple && last.1 == time,
+        };
+
+        if is_duplicate {
+            consolidated.last_mut().unwrap().2 += diff;
+        } else {
+            consolidated.push((tuple, time, diff));
+        }
+    }
+
+    consolidated.retain(|(_tuple, _time, diff)| *diff != 0);
+    consolidated
+}
@@ -96,11 +96,15 @@ impl<A: AsAid, S: Scope<Timestamp = Duration>> Sourceable<A, S> for CsvFile<A> {
 
             // Grab scheduler handle for deferred re-activation.
             let scheduler = context.scheduler;
+            let alive = context.alive;
             let t0 = context.t0;
             let interval = self.interval.unwrap_or(Duration::from_secs(1));
 
             move |_frontiers| {
-                if iterator.reader().is_done() {
+                if alive.upgrade().is_none() {
+                    info!("[W{}] closing after {} datums", worker_index, num_datums_read);
+                    capabilities.drain(..);
+                } else if iterator.reader().is_done() {
                     info!(
                         "[W{}] read {} out of {} datums",
                         worker_index, num_datums_read, datum_index
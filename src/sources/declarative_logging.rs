@@ -1,53 +1,43 @@
 //! Operator and utilities to source data from the underlying
 //! Declarative logging streams.
 
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::{Rc, Weak};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
+use timely::communication::message::RefOrMut;
 use timely::dataflow::channels::pact::Pipeline;
-use timely::dataflow::operators::capture::event::link::EventLink;
 use timely::dataflow::operators::capture::Replay;
 use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
 use timely::dataflow::{Scope, Stream};
-use timely::logging::BatchLogger;
 
 use crate::logging::DeclarativeEvent;
-use crate::scheduling::Scheduler;
-use crate::sources::Sourceable;
-use crate::{Aid, Value};
+use crate::sources::{Sourceable, SourcingContext};
+use crate::{AsAid, Value};
 use crate::{AttributeConfig, InputSemantics};
-use Value::{Eid, Number};
+use Value::{Aid, Bool, Eid, Number};
 
 /// One or more taps into Declarative logging.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
-pub struct DeclarativeLogging {
+pub struct DeclarativeLogging<A: AsAid> {
     /// The log attributes that should be materialized.
-    pub attributes: Vec<Aid>,
+    pub attributes: Vec<A>,
 }
 
-impl<S: Scope<Timestamp = Duration>> Sourceable<S> for DeclarativeLogging {
+impl<A, S> Sourceable<A, S> for DeclarativeLogging<A>
+where
+    A: AsAid + From<&'static str>,
+    S: Scope<Timestamp = Duration>,
+{
     fn source(
         &self,
         scope: &mut S,
-        _t0: Instant,
-        _scheduler: Weak<RefCell<Scheduler<Duration>>>,
+        context: SourcingContext<S::Timestamp>,
     ) -> Vec<(
-        Aid,
+        A,
         AttributeConfig,
         Stream<S, ((Value, Value), Duration, isize)>,
     )> {
-        let events = Rc::new(EventLink::new());
-        let mut logger = BatchLogger::new(events.clone());
-
-        scope
-            .log_register()
-            .insert::<DeclarativeEvent, _>("declarative", move |time, data| {
-                logger.publish_batch(time, data)
-            });
-
-        let input = Some(events).replay_into(scope);
+        let input = Some(context.declarative_events).replay_into(scope);
 
         let mut demux =
             OperatorBuilder::new("Declarative Logging Demux".to_string(), scope.clone());
@@ -59,38 +49,88 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for DeclarativeLogging {
 
         for aid in self.attributes.iter() {
             let (wrapper, stream) = demux.new_output();
-            wrappers.insert(aid.to_string(), wrapper);
-            streams.insert(aid.to_string(), stream);
+            wrappers.insert(aid.clone(), wrapper);
+            streams.insert(aid.clone(), stream);
         }
 
         let mut demux_buffer = Vec::new();
         let num_interests = self.attributes.len();
 
+        let join__tuples = A::from("declarative.event.join/tuples");
+        let rule__implemented = A::from("declarative.event.rule/implemented?");
+        let rule__shutdown = A::from("declarative.event.rule/shutdown?");
+        let hector__extender = A::from("declarative.event.hector/extender");
+        let hector__stage_tuples = A::from("declarative.event.hector/stage-tuples");
+        let trace__size = A::from("declarative.event.trace/size");
+        let tx__batch_size = A::from("declarative.event.tx/batch-size");
+
         demux.build(move |_capability| {
             move |_frontiers| {
                 let mut handles = HashMap::with_capacity(num_interests);
                 for (aid, wrapper) in wrappers.iter_mut() {
-                    handles.insert(aid.to_string(), wrapper.activate());
+                    handles.insert(aid.clone(), wrapper.activate());
                 }
 
-                input.for_each(|time, data| {
+                input.for_each(|time, data: RefOrMut<Vec<_>>| {
                     data.swap(&mut demux_buffer);
 
                     let mut sessions = HashMap::with_capacity(num_interests);
                     for (aid, handle) in handles.iter_mut() {
-                        sessions.insert(aid.to_string(), handle.session(&time));
+                        sessions.insert(aid.clone(), handle.session(&time));
                     }
 
                     for (time, worker, datum) in demux_buffer.drain(..) {
+                        let worker = Eid(worker as u64);
+
                         match datum {
                             DeclarativeEvent::JoinTuples(x) => {
-                                let worker = Eid(worker as u64);
-                                let cardinality = Number(x.cardinality as i64);
+                                let cardinality = Number(x.cardinality);
 
                                 sessions
-                                    .get_mut("declarative.event.join/tuples")
+                                    .get_mut(&join__tuples)
                                     .map(|s| s.give(((worker, cardinality), time, 1)));
                             }
+                            DeclarativeEvent::RuleImplemented(x) => {
+                                sessions
+                                    .get_mut(&rule__implemented)
+                                    .map(|s| s.give(((Aid(x.name), Bool(true)), time, 1)));
+                            }
+                            DeclarativeEvent::RuleShutdown(x) => {
+                                sessions
+                                    .get_mut(&rule__shutdown)
+                                    .map(|s| s.give(((Aid(x.name), Bool(true)), time, 1)));
+                            }
+                            DeclarativeEvent::ExtenderChosen(x) => {
+                                let source = Aid(x.source_attribute);
+                                let extender = Value::String(x.extender);
+
+                                sessions
+                                    .get_mut(&hector__extender)
+                                    .map(|s| s.give(((source, extender), time, 1)));
+                            }
+                            DeclarativeEvent::StageTuples(x) => {
+                                // Stages are identified by their delta
+                                // source and their position within it.
+                                let stage = Value::String(format!(
+                                    "{}/{}",
+                                    x.source_attribute, x.stage
+                                ));
+                                let cardinality = Number(x.cardinality);
+
+                                sessions
+                                    .get_mut(&hector__stage_tuples)
+                                    .map(|s| s.give(((stage, cardinality), time, 1)));
+                            }
+                            DeclarativeEvent::TraceSize(x) => {
+                                sessions
+                                    .get_mut(&trace__size)
+                                    .map(|s| s.give(((Aid(x.name), Number(x.size)), time, 1)));
+                            }
+                            DeclarativeEvent::TxBatch(x) => {
+                                sessions
+                                    .get_mut(&tx__batch_size)
+                                    .map(|s| s.give(((worker, Number(x.size)), time, 1)));
+                            }
                         }
                     }
                 });
@@ -100,9 +140,17 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for DeclarativeLogging {
         self.attributes
             .iter()
             .map(|aid| {
+                // Trace sizes are reported as absolute values, so
+                // only the most recent one is of interest.
+                let semantics = if *aid == A::from("declarative.event.trace/size") {
+                    InputSemantics::LastWriteWins
+                } else {
+                    InputSemantics::Raw
+                };
+
                 (
-                    aid.to_string(),
-                    AttributeConfig::real_time(InputSemantics::Raw),
+                    aid.clone(),
+                    AttributeConfig::real_time(semantics),
                     streams.remove(aid).unwrap(),
                 )
             })
@@ -12,13 +12,14 @@ use timely::progress::Timestamp;
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::logging::DifferentialEvent;
 
+use crate::logging::DeclarativeEvent;
 use crate::scheduling::Scheduler;
 use crate::AttributeConfig;
 use crate::{AsAid, Value};
 
 #[cfg(feature = "csv-source")]
 pub mod csv_file;
-// pub mod declarative_logging;
+pub mod declarative_logging;
 pub mod differential_logging;
 // pub mod json_file;
 pub mod timely_logging;
@@ -37,10 +38,15 @@ pub struct SourcingContext<T: Timestamp> {
     /// A weak handle to a scheduler, used by sources to defer their
     /// next activation when polling.
     pub scheduler: Weak<RefCell<Scheduler<T>>>,
+    /// Sources must release their capabilities once this can't be
+    /// upgraded anymore, e.g. because the server is shutting down.
+    pub alive: Weak<()>,
     /// A weak handle to a Timely event link.
     pub timely_events: Rc<EventLink<Duration, (Duration, usize, TimelyEvent)>>,
     /// A weak handle to Differential event link.
     pub differential_events: Rc<EventLink<Duration, (Duration, usize, DifferentialEvent)>>,
+    /// A weak handle to Declarative event link.
+    pub declarative_events: Rc<EventLink<Duration, (Duration, usize, DeclarativeEvent)>>,
 }
 
 /// An external data source that can provide Datoms.
@@ -70,8 +76,8 @@ pub enum Source<A: AsAid + From<&'static str>> {
     TimelyLogging(timely_logging::TimelyLogging<A>),
     /// Differential logging streams
     DifferentialLogging(differential_logging::DifferentialLogging<A>),
-    // /// Declarative logging streams
-    // DeclarativeLogging(declarative_logging::DeclarativeLogging),
+    /// Declarative logging streams
+    DeclarativeLogging(declarative_logging::DeclarativeLogging<A>),
     /// CSV files
     #[cfg(feature = "csv-source")]
     CsvFile(CsvFile<A>),
@@ -97,7 +103,7 @@ where
         match *self {
             Source::TimelyLogging(ref source) => source.source(scope, context),
             Source::DifferentialLogging(ref source) => source.source(scope, context),
-            // Source::DeclarativeLogging(ref source) => source.source(scope, context),
+            Source::DeclarativeLogging(ref source) => source.source(scope, context),
             #[cfg(feature = "csv-source")]
             Source::CsvFile(ref source) => source.source(scope, context),
             _ => unimplemented!(),
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::mpsc::channel;
+
+use timely::communication::Allocate;
+use timely::worker::Worker;
+
+use declarative_dataflow::server::{Register, Server};
+use declarative_dataflow::timestamp::Time;
+use declarative_dataflow::{Aid, AttributeConfig, Datom, InputSemantics, Plan, Rule, Value};
+use Time::TxId;
+use Value::{Eid, String};
+
+/// Sets up `:edge` and `:banned` attributes, as well as a constraint
+/// forbidding any `:banned` datoms.
+fn no_banned<Al: Allocate>(worker: &mut Worker<Al>, server: &mut Server<Aid, u64, u64>) {
+    worker.dataflow::<u64, _, _>(|scope| {
+        server
+            .create_attribute(scope, ":edge", AttributeConfig::tx_time(InputSemantics::Raw))
+            .unwrap();
+
+        server
+            .create_attribute(scope, ":banned", AttributeConfig::tx_time(InputSemantics::Raw))
+            .unwrap();
+    });
+
+    server
+        .register(Register {
+            rules: vec![Rule::named("no-banned", Plan::match_a(0, ":banned", 1))],
+            publish: vec![],
+            replace: None,
+        })
+        .unwrap();
+
+    worker.dataflow::<u64, _, _>(|scope| {
+        server
+            .register_constraint("no-banned".to_string(), scope)
+            .unwrap();
+    });
+}
+
+#[test]
+fn reject_violating_transactions() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":edge", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+
+            server
+                .create_attribute(scope, ":banned", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule::named("no-banned", Plan::match_a(0, ":banned", 1))],
+                publish: vec![],
+                replace: None,
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .register_constraint("no-banned".to_string(), scope)
+                .unwrap();
+        });
+
+        let allowed = vec![Datom::add(1, ":edge", Eid(2))];
+        server.watch_constraints(&allowed, 0, 0, client, 0).unwrap();
+        server.transact(allowed, 0, 0).unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert!(server.check_constraints().unwrap().is_empty());
+
+        let violating = vec![Datom::add(1, ":banned", Eid(2))];
+        server.watch_constraints(&violating, 0, 0, client, 1).unwrap();
+        server.transact(violating, 0, 0).unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let rejected = server.check_constraints().unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].0, client);
+        assert_eq!(rejected[0].1, 1);
+        assert_eq!(rejected[0].2.category, "df.error.category/conflict");
+
+        // The retraction itself must not trip the constraint again.
+        server.advance_domain(None, 3).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert!(server.check_constraints().unwrap().is_empty());
+    });
+}
+
+#[test]
+fn reject_only_violating_transactions() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        no_banned(worker, &mut server);
+
+        let allowed = vec![Datom::add(1, ":edge", Eid(2))];
+        server.watch_constraints(&allowed, 0, 0, client, 0).unwrap();
+        server.transact(allowed, 0, 0).unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+
+        let violating = vec![Datom::add(1, ":banned", Eid(2))];
+        server.watch_constraints(&violating, 0, 0, client, 1).unwrap();
+        server.transact(violating, 0, 0).unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        // Both transactions are checked at once, but only the
+        // violating one is rejected.
+        let rejected: Vec<u64> = server
+            .check_constraints()
+            .unwrap()
+            .into_iter()
+            .map(|(_client, tx, _error)| tx)
+            .collect();
+
+        assert_eq!(rejected, vec![1]);
+    });
+}
+
+#[test]
+fn refuse_transactions_sharing_a_time() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        no_banned(worker, &mut server);
+
+        let allowed = vec![Datom::add(1, ":edge", Eid(2))];
+        server.watch_constraints(&allowed, 0, 0, client, 0).unwrap();
+        server.transact(allowed, 0, 0).unwrap();
+
+        // Violations couldn't be told apart, so a second transaction
+        // at the same time is refused.
+        let error = server
+            .watch_constraints(&[Datom::add(1, ":edge", Eid(3))], 0, 0, client, 1)
+            .unwrap_err();
+
+        assert_eq!(error.category, "df.error.category/conflict");
+
+        // Requests belonging to the same transaction are fine.
+        server
+            .watch_constraints(&[Datom::add(1, ":edge", Eid(4))], 0, 0, client, 0)
+            .unwrap();
+    });
+}
+
+#[test]
+fn reject_violations_at_explicit_times() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        no_banned(worker, &mut server);
+
+        // The transaction becomes visible at 5, but violates the
+        // constraint at 3 already.
+        let violating = vec![
+            Datom(Eid(1), ":banned".to_string(), Eid(2), Some(TxId(3)), 1),
+            Datom(Eid(1), ":edge".to_string(), Eid(2), Some(TxId(5)), 1),
+        ];
+        server.watch_constraints(&violating, 0, 0, client, 0).unwrap();
+        server.transact(violating, 0, 0).unwrap();
+
+        server.advance_domain(None, 6).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(server.check_constraints().unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn reject_last_write_wins() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+        let client = 7;
+
+        no_banned(worker, &mut server);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(
+                    scope,
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::LastWriteWins),
+                )
+                .unwrap();
+        });
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(scope, Rule::named("names", Plan::match_a(0, ":name", 1)))
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server
+            .transact(vec![Datom::add(1, ":name", String("Mabel".to_string()))], 0, 0)
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let violating = vec![
+            Datom::add(1, ":name", String("Dipper".to_string())),
+            Datom::add(1, ":banned", Eid(2)),
+        ];
+        server.watch_constraints(&violating, 0, 0, client, 1).unwrap();
+        server.transact(violating, 0, 0).unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(server.check_constraints().unwrap().len(), 1);
+
+        server.advance_domain(None, 3).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let mut names = HashMap::new();
+        for (tuple, diff) in results.try_iter() {
+            *names.entry(tuple).or_insert(0) += diff;
+        }
+        names.retain(|_tuple, diff| *diff != 0);
+
+        // The rejected write is retracted, but the value it replaced
+        // is not restored.
+        assert!(names.is_empty());
+    });
+}
+
+#[test]
+fn redefining_drops_constraints() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        no_banned(worker, &mut server);
+
+        server
+            .register(Register {
+                rules: vec![Rule::named("no-banned", Plan::match_a(0, ":edge", 1))],
+                publish: vec![],
+                replace: Some(true),
+            })
+            .unwrap();
+
+        // Neither the old nor the new definition is enforced, until
+        // the constraint is registered again.
+        let banned = vec![Datom::add(1, ":banned", Eid(2))];
+        server.watch_constraints(&banned, 0, 0, client, 0).unwrap();
+        server.transact(banned, 0, 0).unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert!(server.check_constraints().unwrap().is_empty());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .register_constraint("no-banned".to_string(), scope)
+                .unwrap();
+        });
+
+        let edge = vec![Datom::add(1, ":edge", Eid(3))];
+        server.watch_constraints(&edge, 0, 0, client, 1).unwrap();
+        server.transact(edge, 0, 0).unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(server.check_constraints().unwrap().len(), 1);
+    });
+}
@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+
+use declarative_dataflow::server::{Register, Server};
+use declarative_dataflow::sources::declarative_logging::DeclarativeLogging;
+use declarative_dataflow::{Aid, AttributeConfig, Datom, InputSemantics, Plan, Rule, Value};
+use Value::{Bool, Eid, Number};
+
+#[test]
+fn declarative_logging() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        server.enable_logging(worker).unwrap();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":edge", AttributeConfig::real_time(InputSemantics::Raw))
+                .unwrap();
+
+            let source = DeclarativeLogging {
+                attributes: vec![
+                    "declarative.event.rule/implemented?".to_string(),
+                    "declarative.event.tx/batch-size".to_string(),
+                ],
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule::named("edges", Plan::match_a(0, ":edge", 1)),
+                    Rule::named(
+                        "implemented",
+                        Plan::match_a(0, "declarative.event.rule/implemented?", 1),
+                    ),
+                    Rule::named(
+                        "batches",
+                        Plan::match_a(0, "declarative.event.tx/batch-size", 1),
+                    ),
+                ],
+                publish: vec![],
+                replace: None,
+            })
+            .unwrap();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            server.interest("edges".to_string(), scope).unwrap();
+
+            for name in vec!["implemented", "batches"] {
+                let send_results = send_results.clone();
+
+                server
+                    .interest(name.to_string(), scope)
+                    .unwrap()
+                    .inner
+                    .sink(Pipeline, "Results", move |input| {
+                        input.for_each(|_time, data| {
+                            for (tuple, _time, diff) in data.iter() {
+                                send_results.send((name, tuple.clone(), *diff)).unwrap();
+                            }
+                        });
+                    });
+            }
+        });
+
+        server
+            .transact(vec![Datom::add(1, ":edge", Eid(2))], 0, 0)
+            .unwrap();
+
+        let mut expected = HashSet::new();
+        expected.insert(("implemented", vec![Value::Aid("edges".to_string()), Bool(true)]));
+        expected.insert(("batches", vec![Eid(0), Number(1)]));
+
+        // Logging streams are timestamped with real time, so we keep
+        // moving the domain along until all events have shown up.
+        let t0 = Instant::now();
+        while !expected.is_empty() && t0.elapsed() < Duration::from_secs(10) {
+            server.advance_domain(None, t0.elapsed()).unwrap();
+            worker.step();
+
+            while let Ok((name, tuple, diff)) = results.try_recv() {
+                if diff > 0 {
+                    expected.remove(&(name, tuple));
+                }
+            }
+        }
+
+        assert!(expected.is_empty(), "Missing events {:?}", expected);
+    });
+}
@@ -1,10 +1,10 @@
 use std::sync::mpsc::channel;
 
 use declarative_dataflow::plan::{Join, Project};
-use declarative_dataflow::server::Server;
+use declarative_dataflow::server::{Register, Server};
 use declarative_dataflow::timestamp::Time;
 use declarative_dataflow::{Aid, Datom, Plan, Rule, Value};
-use declarative_dataflow::{AttributeConfig, IndexDirection, QuerySupport};
+use declarative_dataflow::{AttributeConfig, IndexDirection, InputSemantics, QuerySupport};
 use Value::{Eid, String};
 
 #[test]
@@ -156,3 +156,133 @@ fn join_after_input() {
         assert_eq!(results.recv().unwrap(), (vec![Eid(101), Eid(1)], 1));
     });
 }
+
+#[test]
+fn redefine_rule() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":name", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        let register = |plan: Plan<Aid>, replace: Option<bool>| Register {
+            rules: vec![Rule::named("q", plan)],
+            publish: vec!["q".to_string()],
+            replace,
+        };
+
+        server
+            .register(register(Plan::match_a(0, ":name", 1), None))
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server.interest("q".to_string(), scope).unwrap();
+        });
+
+        // Registering the same definition again is fine.
+        assert!(server
+            .register(register(Plan::match_a(0, ":name", 1), None))
+            .unwrap()
+            .is_empty());
+
+        // Redefinitions must be requested explicitly.
+        let error = server
+            .register(register(Plan::match_a(1, ":name", 0), None))
+            .unwrap_err();
+        assert_eq!(error.category, "df.error.category/conflict");
+
+        let evicted = server
+            .register(register(Plan::match_a(1, ":name", 0), Some(true)))
+            .unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].0, "q".to_string());
+
+        assert_eq!(
+            server.internal.rule(&"q".to_string()).unwrap().plan,
+            Plan::match_a(1, ":name", 0)
+        );
+    });
+}
+
+#[test]
+fn unregister_and_drop_attribute() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":name", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule::named("names", Plan::match_a(0, ":name", 1)),
+                    Rule::named("all-names", Plan::NameExpr(vec![0, 1], "names".to_string())),
+                ],
+                publish: vec!["all-names".to_string()],
+                replace: None,
+            })
+            .unwrap();
+
+        // Both the attribute and the first rule are still in use.
+        assert_eq!(
+            server
+                .drop_attribute(":name".to_string())
+                .unwrap_err()
+                .category,
+            "df.error.category/conflict"
+        );
+        assert_eq!(
+            server.unregister("names".to_string()).unwrap_err().category,
+            "df.error.category/conflict"
+        );
+
+        server.unregister("all-names".to_string()).unwrap();
+        server.unregister("names".to_string()).unwrap();
+        server.drop_attribute(":name".to_string()).unwrap();
+
+        assert!(!server.internal.has_attribute(&":name".to_string()));
+        assert!(server.internal.forward_propose.is_empty());
+    });
+}
+
+#[test]
+fn create_attribute_idempotent() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":name", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+
+            // Repeating the same definition is fine...
+            server
+                .create_attribute(scope, ":name", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+
+            // ...but a different one is not.
+            assert_eq!(
+                server
+                    .create_attribute(
+                        scope,
+                        ":name",
+                        AttributeConfig::tx_time(InputSemantics::LastWriteWins)
+                    )
+                    .unwrap_err()
+                    .category,
+                "df.error.category/conflict"
+            );
+        });
+
+        assert_eq!(
+            server.internal.attributes[&":name".to_string()],
+            AttributeConfig::tx_time(InputSemantics::Raw)
+        );
+    });
+}
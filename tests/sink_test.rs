@@ -0,0 +1,345 @@
+use std::sync::mpsc::channel;
+
+use timely::communication::Allocate;
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Input;
+use timely::dataflow::ProbeHandle;
+use timely::worker::Worker;
+
+use declarative_dataflow::server::{Register, Server};
+use declarative_dataflow::sinks::{Sinkable, SinkingContext};
+use declarative_dataflow::{Aid, AttributeConfig, Datom, InputSemantics, Plan, ResultDiff, Rule, Value};
+use Value::{Eid, String};
+
+/// A fresh path in the temporary directory.
+fn temp_path(name: &str) -> std::string::String {
+    let path = std::env::temp_dir().join(format!("3df-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+
+    path.to_string_lossy().into_owned()
+}
+
+/// Steps the worker until the probe has passed the given time, and
+/// a little longer, s.t. sinks get to see the new frontier.
+fn settle<Al: Allocate>(worker: &mut Worker<Al>, probe: &ProbeHandle<u64>, time: u64) {
+    worker.step_while(|| probe.less_than(&time));
+
+    for _ in 0..10 {
+        worker.step();
+    }
+}
+
+/// Feeds results into the given sink, returning the input handle.
+fn sink_results<Al, Sk>(
+    worker: &mut Worker<Al>,
+    sink: Sk,
+    probe: &mut ProbeHandle<u64>,
+) -> timely::dataflow::InputHandle<u64, ResultDiff<u64>>
+where
+    Al: Allocate,
+    Sk: Sinkable<u64>,
+{
+    worker.dataflow::<u64, _, _>(|scope| {
+        let (input, stream) = scope.new_input::<ResultDiff<u64>>();
+        let context = SinkingContext {
+            name: "results".to_string(),
+            granularity: None,
+        };
+
+        sink.sink(&stream, Pipeline, probe, context).unwrap();
+
+        input
+    })
+}
+
+#[test]
+fn sink_into_attribute() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":edge", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule::named("edges", Plan::match_a(0, ":edge", 1))],
+                publish: vec!["edges".to_string()],
+                replace: None,
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let relation = server.interest("edges".to_string(), scope).unwrap();
+
+            server
+                .sink_attribute(
+                    scope,
+                    &relation.inner,
+                    Pipeline,
+                    ":derived/edge".to_string(),
+                    0,
+                    1,
+                )
+                .unwrap();
+        });
+
+        assert!(server.internal.has_attribute(&":derived/edge".to_string()));
+
+        server
+            .transact(vec![Datom::add(1, ":edge", Eid(2))], 0, 0)
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        server.transact_sunk().unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(scope, Rule::named("derived", Plan::match_a(0, ":derived/edge", 1)))
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server.advance_domain(None, 3).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(results.recv().unwrap(), (vec![Eid(1), Eid(2)], 1));
+
+        // Retractions must make their way into the sunk attribute as
+        // well.
+        server
+            .transact(vec![Datom::retract(1, ":edge", Eid(2))], 0, 0)
+            .unwrap();
+
+        server.advance_domain(None, 4).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        server.transact_sunk().unwrap();
+
+        server.advance_domain(None, 5).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(results.recv().unwrap(), (vec![Eid(1), Eid(2)], -1));
+    });
+}
+
+#[test]
+fn sink_into_attribute_failures() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":edge", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule::named("edges", Plan::match_a(0, ":edge", 1))],
+                publish: vec!["edges".to_string()],
+                replace: None,
+            })
+            .unwrap();
+
+        // One sink with bogus offsets, one whose attribute will be
+        // gone by the time its results are transacted, and a healthy
+        // one.
+        for (name, v_offset) in &[(":bogus", 5), (":dropped", 1), (":healthy", 1)] {
+            worker.dataflow::<u64, _, _>(|scope| {
+                let relation = server.interest("edges".to_string(), scope).unwrap();
+
+                server
+                    .sink_attribute(scope, &relation.inner, Pipeline, name.to_string(), 0, *v_offset)
+                    .unwrap();
+            });
+        }
+
+        server
+            .transact(vec![Datom::add(1, ":edge", Eid(2))], 0, 0)
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        server.internal.close_input(":dropped".to_string()).unwrap();
+
+        assert!(server.transact_sunk().is_err());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(scope, Rule::named("healthy", Plan::match_a(0, ":healthy", 1)))
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(results.recv().unwrap(), (vec![Eid(1), Eid(2)], 1));
+        assert!(results.try_recv().is_err());
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn csv_file_diffs() {
+    use declarative_dataflow::sinks::CsvFile;
+
+    let path = temp_path("diffs.csv");
+    // Every worker writes to its own file.
+    let file = format!("{}.0", path);
+    let sink = CsvFile {
+        path: path.clone(),
+        header: Some(vec!["name".to_string()]),
+        delimiter: b',',
+        flexible: false,
+        snapshots: false,
+    };
+
+    timely::execute_directly(move |worker| {
+        let mut probe = ProbeHandle::new();
+        let mut input = sink_results(worker, sink, &mut probe);
+
+        input.send((vec![String("Mabel".to_string())], 0, 1));
+        input.send((vec![String("Dipper".to_string())], 0, 1));
+        input.advance_to(1);
+        input.send((vec![String("Dipper".to_string())], 1, -1));
+        settle(worker, &probe, 1);
+
+        // Results at times not yet closed are held back.
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "name,time,diff\nDipper,0,1\nMabel,0,1\n"
+        );
+
+        input.advance_to(2);
+        settle(worker, &probe, 2);
+
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "name,time,diff\nDipper,0,1\nMabel,0,1\nDipper,1,-1\n"
+        );
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn csv_file_snapshots() {
+    use declarative_dataflow::sinks::CsvFile;
+
+    let path = temp_path("snapshots.csv");
+    // Every worker writes to its own file.
+    let file = format!("{}.0", path);
+    let sink = CsvFile {
+        path: path.clone(),
+        header: None,
+        delimiter: b';',
+        flexible: false,
+        snapshots: true,
+    };
+
+    timely::execute_directly(move |worker| {
+        let mut probe = ProbeHandle::new();
+        let mut input = sink_results(worker, sink, &mut probe);
+
+        input.send((vec![String("Dipper".to_string()), Eid(1)], 0, 1));
+        input.send((vec![String("Mabel".to_string()), Eid(2)], 0, 1));
+        input.advance_to(1);
+        input.send((vec![String("Dipper".to_string()), Eid(1)], 1, -1));
+        input.advance_to(2);
+        settle(worker, &probe, 2);
+
+        // Every closed time gets the full result, without a header.
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "Dipper;1;0;1\nMabel;2;0;1\nMabel;2;1;1\n"
+        );
+    });
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn json_lines_plain_times() {
+    use declarative_dataflow::sinks::JsonLines;
+    use declarative_dataflow::Rational32;
+
+    let path = temp_path("results.jsonl");
+    // The first segment written by the first worker.
+    let segment = format!("{}.0.0", path);
+    std::fs::remove_file(&segment).ok();
+
+    let sink = JsonLines {
+        path: path.clone(),
+        columns: vec!["name".to_string(), "share".to_string()],
+        max_bytes: None,
+        max_age: None,
+    };
+
+    timely::execute_directly(move |worker| {
+        let mut probe = ProbeHandle::new();
+        let mut input = sink_results(worker, sink, &mut probe);
+
+        let share = Value::Rational32(Rational32::new(1, 2));
+
+        input.send((vec![String("Mabel".to_string()), share.clone()], 0, 1));
+        input.advance_to(1);
+        input.send((vec![String("Mabel".to_string()), share], 1, -1));
+        input.advance_to(2);
+        settle(worker, &probe, 2);
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&segment)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        // Times are written as plain numbers, just like in csv files,
+        // and so are rationals.
+        assert_eq!(
+            lines,
+            vec![
+                serde_json::json!({"name": "Mabel", "share": 0.5, "time": 0, "diff": 1}),
+                serde_json::json!({"name": "Mabel", "share": 0.5, "time": 1, "diff": -1}),
+            ]
+        );
+    });
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn json_lines_reserved_columns() {
+    use declarative_dataflow::sinks::JsonLines;
+
+    let sink = JsonLines {
+        path: temp_path("reserved.jsonl"),
+        columns: vec!["name".to_string(), "time".to_string()],
+        max_bytes: None,
+        max_age: None,
+    };
+
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<u64, _, _>(|scope| {
+            let (_input, stream) = scope.new_input::<ResultDiff<u64>>();
+            let context = SinkingContext {
+                name: "results".to_string(),
+                granularity: None,
+            };
+
+            assert!(sink
+                .sink(&stream, Pipeline, &mut ProbeHandle::new(), context)
+                .is_err());
+        });
+    });
+}
@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+
+use timely::dataflow::operators::Probe;
+
+use declarative_dataflow::server::{Register, Server};
+use declarative_dataflow::{Aid, AttributeConfig, Datom, InputSemantics, Plan, Rule, Value};
+use Value::{Eid, Number, String};
+
+#[test]
+fn subscription_lifecycle() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":name", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        let probe = worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .subscribe(":name".to_string(), scope)
+                .unwrap()
+                .inner
+                .probe()
+        });
+
+        server
+            .interests
+            .entry(":name".to_string())
+            .or_insert_with(HashSet::new)
+            .insert(client);
+
+        server
+            .transact(vec![Datom::add(1, ":name", String("Dipper".to_string()))], 0, 0)
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| probe.less_than(&1));
+
+        assert!(!probe.done());
+
+        // Once the last subscriber is gone, the trace import must be
+        // released, which completes the subscription dataflow.
+        server.disconnect_client(client).unwrap();
+        worker.step_while(|| !probe.done());
+
+        assert!(probe.done());
+        assert!(!server.interests.contains_key(&":name".to_string()));
+    });
+}
+
+#[test]
+fn resume_subscription() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let config = AttributeConfig {
+                trace_slack: None,
+                ..AttributeConfig::tx_time(InputSemantics::Raw)
+            };
+
+            server.create_attribute(scope, ":name", config).unwrap();
+            server
+                .create_attribute(scope, ":age", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule::named("names", Plan::match_a(0, ":name", 1)),
+                    Rule::named("ages", Plan::match_a(0, ":age", 1)),
+                ],
+                publish: vec!["names".to_string(), "ages".to_string()],
+                replace: None,
+            })
+            .unwrap();
+
+        server
+            .transact(
+                vec![
+                    Datom::add(1, ":name", String("Dipper".to_string())),
+                    Datom::add(1, ":age", Number(12)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+        server.advance_domain(None, 1).unwrap();
+
+        server
+            .transact(
+                vec![
+                    Datom::add(2, ":name", String("Mabel".to_string())),
+                    Datom::add(2, ":age", Number(12)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+        server.advance_domain(None, 3).unwrap();
+        server.internal.advance().unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .subscription("names@1".to_string(), "names".to_string(), Some(0), scope)
+                .unwrap()
+                .inspect(move |x| send_results.send(x.clone()).unwrap())
+                .probe_with(&mut server.probe);
+        });
+
+        worker.step_while(|| server.is_any_outdated());
+
+        // Only what happened after the resumed time is produced.
+        assert_eq!(
+            results.recv().unwrap(),
+            (vec![Eid(2), String("Mabel".to_string())], 1, 1)
+        );
+        assert!(results.try_recv().is_err());
+
+        // The rule can't go away underneath the subscription.
+        assert_eq!(
+            server.unregister("names".to_string()).unwrap_err().category,
+            "df.error.category/conflict"
+        );
+
+        // The history of :age has been compacted since.
+        let error = worker
+            .dataflow::<u64, _, _>(|scope| {
+                server
+                    .subscription("ages@1".to_string(), "ages".to_string(), Some(0), scope)
+                    .map(|_relation| ())
+            })
+            .unwrap_err();
+
+        assert_eq!(error.category, "df.error.category/snapshot-required");
+    });
+}
+
+#[test]
+fn shared_results() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let (send_early, early) = channel();
+        let (send_late, late) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":name", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule::named("names", Plan::match_a(0, ":name", 1))],
+                publish: vec!["names".to_string()],
+                replace: None,
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server.share("names".to_string(), scope).unwrap();
+        });
+
+        for client in [1, 2].iter() {
+            server
+                .interests
+                .entry("names".to_string())
+                .or_insert_with(HashSet::new)
+                .insert(*client);
+        }
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .view("names".to_string(), 1, scope)
+                .unwrap()
+                .inspect(move |x| send_early.send((x.0.clone(), x.2)).unwrap())
+                .probe_with(&mut server.probe);
+        });
+
+        server
+            .transact(vec![Datom::add(1, ":name", String("Dipper".to_string()))], 0, 0)
+            .unwrap();
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(
+            early.recv().unwrap(),
+            (vec![Eid(1), String("Dipper".to_string())], 1)
+        );
+
+        // Views attached later start out with everything so far.
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .view("names".to_string(), 2, scope)
+                .unwrap()
+                .inspect(move |x| send_late.send((x.0.clone(), x.2)).unwrap())
+                .probe_with(&mut server.probe);
+        });
+
+        server
+            .transact(vec![Datom::add(2, ":name", String("Mabel".to_string()))], 0, 0)
+            .unwrap();
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let mut seen: Vec<_> = late.try_iter().collect();
+        seen.sort();
+
+        assert_eq!(
+            seen,
+            vec![
+                (vec![Eid(1), String("Dipper".to_string())], 1),
+                (vec![Eid(2), String("Mabel".to_string())], 1),
+            ]
+        );
+
+        // The shared results go away with the last interested client.
+        server.uninterest(1, &"names".to_string()).unwrap();
+        assert!(server.interests.contains_key(&"names".to_string()));
+
+        server.uninterest(2, &"names".to_string()).unwrap();
+        assert!(!server.interests.contains_key(&"names".to_string()));
+
+        let error = worker
+            .dataflow::<u64, _, _>(|scope| {
+                server
+                    .view("names".to_string(), 3, scope)
+                    .map(|_relation| ())
+            })
+            .unwrap_err();
+
+        assert_eq!(error.category, "df.error.category/not-found");
+    });
+}
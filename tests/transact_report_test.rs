@@ -0,0 +1,71 @@
+use declarative_dataflow::server::Server;
+use declarative_dataflow::{Aid, AttributeConfig, Datom, InputSemantics, Plan, Rule, Value};
+use Value::Eid;
+
+#[test]
+fn transact_report() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let client = 7;
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":edge", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server.test_single(scope, Rule::named("edges", Plan::match_a(0, ":edge", 1)));
+        });
+
+        let time = server
+            .transact_reported(vec![Datom::add(1, ":edge", Eid(2))], 0, 0, client, 0)
+            .unwrap();
+
+        assert_eq!(time, 0);
+        assert!(server.visible_transactions().is_empty());
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(server.visible_transactions(), vec![(client, 0)]);
+        assert!(server.visible_transactions().is_empty());
+    });
+}
+
+#[test]
+fn transact_all_or_nothing() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Aid, u64, u64>::new(Default::default());
+        let (send_results, results) = std::sync::mpsc::channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .create_attribute(scope, ":edge", AttributeConfig::tx_time(InputSemantics::Raw))
+                .unwrap();
+        });
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(scope, Rule::named("edges", Plan::match_a(0, ":edge", 1)))
+                .inspect(move |x| {
+                    send_results.send(x.0.clone()).unwrap();
+                });
+        });
+
+        let error = server
+            .transact(
+                vec![Datom::add(1, ":edge", Eid(2)), Datom::add(1, ":unknown", Eid(3))],
+                0,
+                0,
+            )
+            .unwrap_err();
+
+        assert_eq!(error.category, "df.error.category/not-found");
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert!(results.try_recv().is_err());
+    });
+}